@@ -1,55 +1,338 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use camino::Utf8PathBuf;
 use scarb::compiler::{CompilerRepository, Profile};
 use scarb::core::{Config, TargetKind};
-use scarb::ops::{CompileOpts, FeaturesOpts, FeaturesSelector};
+use scarb::ops::{CompileOpts, FeaturesOpts, FeaturesSelector, MetadataOptions};
+use scarb_ui::{OutputFormat, Ui, Verbosity};
+use smol_str::SmolStr;
 use std::env;
+use std::num::NonZeroUsize;
 
+use demo_plugin::compiler::Props;
+use demo_plugin::project::compile_cairo_project;
+use demo_plugin::scarb_funcs::{BuildOpts, PackagesSelector, UnitStatus};
 use demo_plugin::{compiler::DemoCompiler, plugin::CairoPluginRepository, scarb_funcs};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Path to the Scarb.toml file
-    #[arg(short, long, value_name = "MANIFEST_FILE_PATH")]
-    manifest_path: Utf8PathBuf,
+    /// Path to the Scarb.toml file, searched from the current directory by default
+    #[arg(short, long, global = true, value_name = "MANIFEST_FILE_PATH")]
+    manifest_path: Option<Utf8PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compiles the workspace
+    Build(BuildArgs),
+    /// Reports the diagnostics of the workspace without compiling it, failing if any unit has
+    /// errors
+    Check(CompileArgs),
+    /// Prints the code generated by the demo plugin for the contracts of the workspace
+    Expand(ExpandArgs),
+    /// Removes the artifacts produced by the demo target
+    Clean,
+    /// Prints the metadata of the workspace, as `scarb metadata` does
+    Metadata(MetadataArgs),
+}
+
+#[derive(Args, Debug)]
+struct BuildArgs {
+    /// Builds the Cairo project of this directory from its cairo_project.toml, without Scarb
+    #[arg(
+        long,
+        value_name = "PROJECT_DIR",
+        conflicts_with_all = [
+            "manifest_path",
+            "jobs",
+            "target_names",
+            "ignore_cairo_version",
+            "packages",
+            "workspace",
+            "features",
+            "all_features",
+            "no_default_features",
+        ]
+    )]
+    cairo_project: Option<Utf8PathBuf>,
+
+    #[command(flatten)]
+    compile: CompileArgs,
+}
+
+#[derive(Args, Debug)]
+struct CompileArgs {
+    /// Number of compilation units analyzed in parallel, defaults to the number of CPUs
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Comma separated list of target names to compile
+    #[arg(long, value_delimiter = ',', value_name = "TARGET_NAMES")]
+    target_names: Vec<SmolStr>,
+
+    /// Compile even if the packages require another Cairo version than the one linked in
+    #[arg(long)]
+    ignore_cairo_version: bool,
+
+    #[command(flatten)]
+    packages: PackagesSpec,
+
+    #[command(flatten)]
+    features: FeaturesSpec,
+}
+
+impl CompileArgs {
+    fn into_opts(self) -> (CompileOpts, BuildOpts) {
+        let opts = CompileOpts {
+            include_target_kinds: vec![],
+            include_target_names: self.target_names,
+            exclude_target_kinds: vec![TargetKind::TEST],
+            features: self.features.into(),
+        };
+
+        let mut build_opts = BuildOpts {
+            packages: self.packages.into(),
+            ignore_cairo_version: self.ignore_cairo_version,
+            ..Default::default()
+        };
+        if let Some(jobs) = self.jobs {
+            build_opts.jobs = jobs;
+        }
+
+        (opts, build_opts)
+    }
+}
+
+#[derive(Args, Debug)]
+struct ExpandArgs {
+    /// Expand even if the packages require another Cairo version than the one linked in
+    #[arg(long)]
+    ignore_cairo_version: bool,
+
+    #[command(flatten)]
+    packages: PackagesSpec,
+
+    #[command(flatten)]
+    features: FeaturesSpec,
+}
+
+#[derive(Args, Debug)]
+struct MetadataArgs {
+    /// The version of the metadata format
+    #[arg(long, value_name = "VERSION", default_value_t = 1)]
+    format_version: u64,
+
+    /// Only output the workspace members, without their dependencies
+    #[arg(long)]
+    no_deps: bool,
+
+    /// Resolve the metadata even if the packages require another Cairo version than the one
+    /// linked in
+    #[arg(long)]
+    ignore_cairo_version: bool,
+
+    #[command(flatten)]
+    features: FeaturesSpec,
+}
+
+/// Packages selection, the same way Scarb does.
+#[derive(Args, Debug)]
+struct PackagesSpec {
+    /// Comma separated list of workspace members to compile
+    #[arg(short, long = "package", value_delimiter = ',', value_name = "SPEC")]
+    packages: Vec<String>,
+
+    /// Compile all the workspace members, which is the default
+    #[arg(long, conflicts_with = "packages")]
+    workspace: bool,
+}
+
+impl From<PackagesSpec> for PackagesSelector {
+    fn from(spec: PackagesSpec) -> Self {
+        if spec.workspace || spec.packages.is_empty() {
+            PackagesSelector::Workspace
+        } else {
+            PackagesSelector::Names(spec.packages)
+        }
+    }
+}
+
+/// Features selection, the same way Scarb does.
+#[derive(Args, Debug)]
+struct FeaturesSpec {
+    /// Comma separated list of features to activate
+    #[arg(short = 'F', long, value_delimiter = ',')]
+    features: Vec<String>,
+
+    /// Activate all available features
+    #[arg(long, conflicts_with_all = ["features", "no_default_features"])]
+    all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[arg(long, conflicts_with = "all_features")]
+    no_default_features: bool,
+}
+
+impl From<FeaturesSpec> for FeaturesOpts {
+    fn from(spec: FeaturesSpec) -> Self {
+        let features = if spec.all_features {
+            FeaturesSelector::AllFeatures
+        } else {
+            FeaturesSelector::Features(spec.features)
+        };
+
+        Self {
+            features,
+            no_default_features: spec.no_default_features,
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let manifest = Utf8PathBuf::from(
-        std::fs::canonicalize(cli.manifest_path)?
-            .as_os_str()
-            .to_string_lossy()
-            .to_string(),
-    );
+    if let Command::Build(BuildArgs {
+        cairo_project: Some(project_dir),
+        ..
+    }) = cli.command
+    {
+        let project_dir = canonicalize(project_dir)?;
+        let ui = Ui::new(Verbosity::Verbose, OutputFormat::Text);
+
+        return compile_cairo_project(
+            &project_dir,
+            &project_dir.join("target"),
+            Props::default(),
+            ui,
+        );
+    }
+
+    let config = scarb_config(cli.manifest_path)?;
+
+    match cli.command {
+        Command::Build(args) => {
+            let (opts, build_opts) = args.compile.into_opts();
+            scarb_funcs::compile_workspace(&config, opts, build_opts)?;
+        }
+        Command::Check(args) => {
+            let (opts, build_opts) = args.into_opts();
+            let units = scarb_funcs::check_workspace(&config, opts, build_opts)?;
+
+            let failed_units = units
+                .iter()
+                .filter(|unit| unit.status == UnitStatus::Failed)
+                .map(|unit| unit.name.as_str())
+                .collect::<Vec<_>>();
+
+            if !failed_units.is_empty() {
+                bail!("could not check units: {}", failed_units.join(", "));
+            }
+        }
+        Command::Expand(args) => {
+            let opts = CompileOpts {
+                include_target_kinds: vec![],
+                include_target_names: vec![],
+                exclude_target_kinds: vec![TargetKind::TEST],
+                features: args.features.into(),
+            };
+            let build_opts = BuildOpts {
+                packages: args.packages.into(),
+                ignore_cairo_version: args.ignore_cairo_version,
+                ..Default::default()
+            };
+
+            scarb_funcs::expand_workspace(&config, opts, build_opts)?;
+        }
+        Command::Clean => scarb_funcs::clean_workspace(&config)?,
+        Command::Metadata(args) => {
+            let ws = scarb::ops::read_workspace(config.manifest_path(), &config)?;
+            let metadata = scarb::ops::collect_metadata(
+                &MetadataOptions {
+                    version: args.format_version,
+                    no_deps: args.no_deps,
+                    features: args.features.into(),
+                    ignore_cairo_version: args.ignore_cairo_version,
+                },
+                &ws,
+            )?;
+
+            println!("{}", serde_json::to_string_pretty(&metadata)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the Scarb configuration shared by the subcommands, with the demo compiler and the
+/// builtin plugins.
+fn scarb_config(manifest_path: Option<Utf8PathBuf>) -> Result<Config> {
+    let manifest = match manifest_path {
+        Some(manifest_path) => canonicalize(manifest_path)?,
+        None => scarb::ops::find_manifest_path(None)?,
+    };
 
     let mut compilers = CompilerRepository::std();
     compilers.add(Box::new(DemoCompiler)).unwrap();
 
-    let config = Config::builder(manifest)
+    Config::builder(manifest)
         .log_filter_directive(env::var_os("SCARB_LOG"))
         .profile(Profile::DEV)
         .offline(false)
         .cairo_plugins(CairoPluginRepository::default().into())
-        .ui_verbosity(scarb_ui::Verbosity::Verbose)
+        .ui_verbosity(Verbosity::Verbose)
         .compilers(compilers)
-        .build()?;
-
-    let opts = CompileOpts {
-        include_target_kinds: vec![],
-        include_target_names: vec![],
-        exclude_target_kinds: vec![TargetKind::TEST],
-        features: FeaturesOpts {
-            features: FeaturesSelector::AllFeatures,
-            no_default_features: false,
-        },
-    };
+        .build()
+}
+
+fn canonicalize(path: Utf8PathBuf) -> Result<Utf8PathBuf> {
+    Ok(Utf8PathBuf::from(
+        std::fs::canonicalize(path)?
+            .as_os_str()
+            .to_string_lossy()
+            .to_string(),
+    ))
+}
 
-    scarb_funcs::compile_workspace(&config, opts)?;
+#[cfg(test)]
+mod tests {
+    use clap::error::ErrorKind;
+    use clap::{CommandFactory, Parser};
 
-    Ok(())
+    use super::Cli;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn cairo_project_rejects_scarb_options() {
+        for option in [
+            &["--manifest-path", "Scarb.toml"][..],
+            &["--jobs", "2"],
+            &["--target-names", "demo"],
+            &["--ignore-cairo-version"],
+            &["--package", "demo"],
+            &["--workspace"],
+            &["--features", "x"],
+            &["--all-features"],
+            &["--no-default-features"],
+        ] {
+            let args = ["compiler", "build", "--cairo-project", "project"]
+                .iter()
+                .chain(option);
+
+            let err = Cli::try_parse_from(args)
+                .err()
+                .unwrap_or_else(|| panic!("`{}` must conflict with `--cairo-project`", option[0]));
+            assert_eq!(err.kind(), ErrorKind::ArgumentConflict);
+        }
+
+        assert!(Cli::try_parse_from(["compiler", "build", "--cairo-project", "project"]).is_ok());
+    }
 }
@@ -0,0 +1,308 @@
+//! The rewrite of the `#[custom::contract]` modules, shared by the builtin demo plugin and the
+//! procedural macro.
+use cairo_lang_defs::patcher::{PatchBuilder, RewriteNode};
+use cairo_lang_defs::plugin::PluginDiagnostic;
+use cairo_lang_filesystem::cfg::CfgSet;
+use cairo_lang_filesystem::ids::CodeMapping;
+use cairo_lang_plugins::plugins::HasItemsInCfgEx;
+use cairo_lang_syntax::node::ast::MaybeModuleBody;
+use cairo_lang_syntax::node::db::SyntaxGroup;
+use cairo_lang_syntax::node::{ast, Terminal, TypedStablePtr, TypedSyntaxNode};
+use cairo_lang_utils::unordered_hash_map::UnorderedHashMap;
+use smol_str::SmolStr;
+
+mod syntax_utils;
+
+/// The result of the rewrite of a contract module.
+#[derive(Debug)]
+pub struct ContractExpansion {
+    /// The name of the module.
+    pub name: SmolStr,
+    /// The code of the rewritten module, replacing the original one.
+    pub code: String,
+    /// Links the rewritten code to the original module.
+    pub code_mappings: Vec<CodeMapping>,
+    pub diagnostics: Vec<PluginDiagnostic>,
+}
+
+/// Rewrites a contract module into a Starknet contract.
+///
+/// The items of the implementations are filtered with `cfg_set` when given. Without it, they are
+/// all kept with their attributes, to be filtered by the compiler once expanded.
+///
+/// Returns `None` if the module has no body.
+pub fn expand_contract_module(
+    db: &dyn SyntaxGroup,
+    module_ast: &ast::ItemModule,
+    cfg_set: Option<&CfgSet>,
+) -> Option<ContractExpansion> {
+    let MaybeModuleBody::Some(body) = module_ast.body(db) else {
+        return None;
+    };
+
+    let name = module_ast.name(db).text(db);
+
+    let mut diagnostics = vec![];
+
+    let mut builder = PatchBuilder::new(db, module_ast);
+
+    let mut body_nodes: Vec<_> = body
+        .items(db)
+        .elements(db)
+        .iter()
+        .flat_map(|el| {
+            if let ast::ModuleItem::Impl(impl_ast) = el {
+                let mut impl_nodes = vec![];
+
+                let impl_name = impl_ast.name(db).text(db);
+                if impl_name == "bad" {
+                    // Try a plugin diagnostic to check diagnostic handling by LS.
+                    diagnostics.push(PluginDiagnostic::error(
+                        impl_ast.stable_ptr().untyped(),
+                        "Invalid impl name".to_string(),
+                    ));
+                }
+
+                // Map the existing impl node as it will be replaced, to have correct
+                // diagnostics.
+                let impl_node = RewriteNode::Mapped {
+                    node: Box::new(RewriteNode::Text(format!(
+                        "{} impl {} of {} {{\n",
+                        impl_ast.attributes(db).as_syntax_node().get_text(db),
+                        impl_name,
+                        impl_ast.trait_path(db).as_syntax_node().get_text(db),
+                    ))),
+                    origin: impl_ast.as_syntax_node().span_without_trivia(db),
+                };
+
+                impl_nodes.push(impl_node);
+
+                if let ast::MaybeImplBody::Some(impl_body) = impl_ast.body(db) {
+                    let items: Vec<_> = match cfg_set {
+                        Some(cfg_set) => impl_body.iter_items_in_cfg(db, cfg_set).collect(),
+                        None => impl_body.items(db).elements(db),
+                    };
+
+                    let body_nodes: Vec<_> = items
+                        .into_iter()
+                        .flat_map(|el| {
+                            if let ast::ImplItem::Function(ref fn_ast) = el {
+                                let mut fn_nodes =
+                                    rewrite_function(db, fn_ast.clone(), &mut diagnostics);
+
+                                // The rewritten declaration drops the attributes, which must be
+                                // kept for the compiler to filter the function by its cfg.
+                                if cfg_set.is_none() {
+                                    fn_nodes.insert(
+                                        0,
+                                        RewriteNode::Copied(fn_ast.attributes(db).as_syntax_node()),
+                                    );
+                                }
+
+                                fn_nodes
+                            } else {
+                                vec![RewriteNode::Copied(el.as_syntax_node())]
+                            }
+                        })
+                        .collect();
+
+                    // Also mapping the body nodes to have correct diagnostics.
+                    let mapped_node = RewriteNode::Mapped {
+                        node: Box::new(RewriteNode::interpolate_patched(
+                            "$body$",
+                            &UnorderedHashMap::from([(
+                                "body".to_string(),
+                                RewriteNode::new_modified(body_nodes),
+                            )]),
+                        )),
+                        origin: impl_ast.as_syntax_node().span_without_trivia(db),
+                    };
+
+                    impl_nodes.push(mapped_node);
+                }
+
+                impl_nodes.push(RewriteNode::Text("\n}".to_string()));
+                return impl_nodes;
+            }
+
+            // Other items are copied as is.
+            vec![RewriteNode::Copied(el.as_syntax_node())]
+        })
+        .collect();
+
+    // Add a standalone struct.
+    body_nodes.append(&mut vec![RewriteNode::Text("\nstruct S {}\n".to_string())]);
+
+    builder.add_modified(RewriteNode::interpolate_patched(
+        "
+            #[starknet::contract]
+            mod $name$ {
+
+                $body$
+
+            }
+            ",
+        &UnorderedHashMap::from([
+            ("name".to_string(), RewriteNode::Text(name.to_string())),
+            ("body".to_string(), RewriteNode::new_modified(body_nodes)),
+        ]),
+    ));
+
+    let (code, code_mappings) = builder.build();
+
+    Some(ContractExpansion {
+        name,
+        code,
+        code_mappings,
+        diagnostics,
+    })
+}
+
+pub fn rewrite_function(
+    db: &dyn SyntaxGroup,
+    fn_ast: ast::FunctionWithBody,
+    diagnostics: &mut Vec<PluginDiagnostic>,
+) -> Vec<RewriteNode> {
+    let fn_name = fn_ast.declaration(db).name(db).text(db);
+    let return_type = fn_ast
+        .declaration(db)
+        .signature(db)
+        .ret_ty(db)
+        .as_syntax_node()
+        .get_text(db);
+
+    let params_str = rewrite_parameters(db, fn_ast.declaration(db).signature(db).parameters(db));
+
+    let declaration_node = RewriteNode::Mapped {
+        node: Box::new(RewriteNode::Text(format!(
+            "fn {}({}) {} {{\n",
+            fn_name, params_str, return_type
+        ))),
+        origin: fn_ast
+            .declaration(db)
+            .as_syntax_node()
+            .span_without_trivia(db),
+    };
+
+    // Add some new statements inside the function before user's ones.
+    let additional_node1 = RewriteNode::Text("let a = 32;\n".to_string());
+    let additional_node2 = RewriteNode::Text("let _b = a + 4;\n".to_string());
+
+    let func_nodes = fn_ast
+        .body(db)
+        .statements(db)
+        .elements(db)
+        .iter()
+        .map(|e| {
+            if e.as_syntax_node()
+                .get_text(db)
+                .trim()
+                .starts_with("let fail = 1")
+            {
+                // Add an other plugin diagnostic inside a function to check plugin diagnostic handling.
+                diagnostics.push(PluginDiagnostic::error(
+                    e.stable_ptr().untyped(),
+                    "Invalid statement starting with 'let fail = 1'".to_string(),
+                ));
+                RewriteNode::Copied(e.as_syntax_node())
+            } else {
+                RewriteNode::Mapped {
+                    node: Box::new(RewriteNode::from(e.as_syntax_node())),
+                    origin: e.as_syntax_node().span_without_trivia(db),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut nodes = vec![declaration_node, additional_node1, additional_node2];
+    nodes.extend(func_nodes);
+    nodes.push(RewriteNode::Text("\n}".to_string()));
+
+    nodes
+}
+
+/// Rewrites the parameters of a function by replacing `r: R` to `ref self: ContractState` if present,
+/// otherwise adding `self: @ContractState` at the beginning.
+///
+/// # Arguments
+///
+/// * `db` - The syntax group.
+/// * `param_list` - The list of parameters.
+/// * `fn_diagnostic_item` - The diagnostic item.
+///
+/// # Returns
+///
+/// * The rewritten parameters as a string.
+pub fn rewrite_parameters(db: &dyn SyntaxGroup, param_list: ast::ParamList) -> String {
+    let mut use_ref = false;
+
+    let mut params = param_list
+        .elements(db)
+        .iter()
+        .filter_map(|param| {
+            let param_info = syntax_utils::get_parameter_info(db, param.clone());
+
+            if &param_info.name == "r" && &param_info.param_type == "R" {
+                use_ref = true;
+                None
+            } else {
+                Some(param.as_syntax_node().get_text(db))
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if use_ref {
+        params.insert(0, "ref self: ContractState".to_string());
+    } else {
+        params.insert(0, "self: @ContractState".to_string());
+    }
+
+    params.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo_lang_parser::utils::SimpleParserDatabase;
+    use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
+
+    use super::expand_contract_module;
+
+    fn parse_module(db: &SimpleParserDatabase, code: &str) -> ast::ItemModule {
+        let node = db.parse_virtual(code).unwrap();
+
+        ast::SyntaxFile::from_syntax_node(db, node)
+            .items(db)
+            .elements(db)
+            .into_iter()
+            .find_map(|item| match item {
+                ast::ModuleItem::Module(module_ast) => Some(module_ast),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_cfg_attributes_of_functions_without_cfg_set() {
+        let db = SimpleParserDatabase::default();
+        let module_ast = parse_module(
+            &db,
+            "
+            mod c {
+                impl I of T {
+                    #[cfg(test)]
+                    fn f() -> u8 { 1 }
+                    #[cfg(not(test))]
+                    fn f() -> u8 { 2 }
+                }
+            }
+            ",
+        );
+
+        let expansion = expand_contract_module(&db, &module_ast, None).unwrap();
+        let code: String = expansion.code.split_whitespace().collect();
+
+        assert!(expansion.diagnostics.is_empty());
+        assert!(code.contains("#[cfg(test)]fnf(self:@ContractState)->u8{"));
+        assert!(code.contains("#[cfg(not(test))]fnf(self:@ContractState)->u8{"));
+    }
+}
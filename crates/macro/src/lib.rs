@@ -0,0 +1,50 @@
+//! The demo plugin as a Scarb procedural macro, usable with the stock `scarb build` and language
+//! server.
+//!
+//! Procedural macros can't be named after a path, the attribute is `#[custom_contract]` instead
+//! of `#[custom::contract]`.
+use cairo_lang_diagnostics::Severity;
+use cairo_lang_macro::{attribute_macro, Diagnostic, Diagnostics, ProcMacroResult, TokenStream};
+use cairo_lang_parser::utils::SimpleParserDatabase;
+use cairo_lang_syntax::node::{ast, TypedSyntaxNode};
+use demo_plugin_core::expand_contract_module;
+
+/// Rewrites a module into a Starknet contract, the same way the builtin plugin does.
+#[attribute_macro]
+pub fn custom_contract(_args: TokenStream, token_stream: TokenStream) -> ProcMacroResult {
+    let db = SimpleParserDatabase::default();
+    let (node, _diagnostics) = db.parse_virtual_with_diagnostics(token_stream.clone());
+
+    let module_ast = ast::SyntaxFile::from_syntax_node(&db, node)
+        .items(&db)
+        .elements(&db)
+        .into_iter()
+        .find_map(|item| match item {
+            ast::ModuleItem::Module(module_ast) => Some(module_ast),
+            _ => None,
+        });
+
+    let Some(module_ast) = module_ast else {
+        return ProcMacroResult::new(token_stream).with_diagnostics(Diagnostics::new(vec![
+            Diagnostic::error("`#[custom_contract]` can only be applied to modules."),
+        ]));
+    };
+
+    // The cfg set of the crate is not known by the procedural macros, the items are kept with their
+    // attributes to be filtered by the compiler once expanded.
+    let Some(expansion) = expand_contract_module(&db, &module_ast, None) else {
+        return ProcMacroResult::new(token_stream);
+    };
+
+    let diagnostics = expansion
+        .diagnostics
+        .into_iter()
+        .map(|diagnostic| match diagnostic.severity {
+            Severity::Error => Diagnostic::error(diagnostic.message),
+            Severity::Warning => Diagnostic::warn(diagnostic.message),
+        })
+        .collect();
+
+    ProcMacroResult::new(TokenStream::new(expansion.code))
+        .with_diagnostics(Diagnostics::new(diagnostics))
+}
@@ -0,0 +1,511 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::ops::{Deref, DerefMut};
+
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use scarb::core::Config;
+use scarb::flock::{FileLockGuard, Filesystem};
+use scarb_ui::Ui;
+use serde::{Deserialize, Serialize};
+
+/// Directory, inside the target directory of a profile, where the demo compiler keeps the state
+/// of each unit between builds.
+pub const STATE_DIR: &str = ".demo";
+
+/// File, inside the state directory of a unit, listing the artifacts of its last build.
+const ARTIFACTS_RECORD_FILE: &str = "artifacts.json";
+
+/// The artifacts produced by the last build of a unit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArtifactsRecord {
+    /// The artifact files, relative to the target directory of the profile so the record stays
+    /// valid when the project is moved.
+    files: BTreeSet<Utf8PathBuf>,
+}
+
+impl ArtifactsRecord {
+    /// Reads a record, an invalid or missing record is considered empty.
+    fn read(path: &Utf8Path) -> Self {
+        Self::try_read(path).unwrap_or_default()
+    }
+
+    fn try_read(path: &Utf8Path) -> Option<Self> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    /// Returns the absolute paths of the artifacts, skipping the ones that would resolve outside of
+    /// the profile directory.
+    fn resolved_files<'a>(
+        &'a self,
+        profile_dir: &'a Utf8Path,
+    ) -> impl Iterator<Item = Utf8PathBuf> + 'a {
+        self.files
+            .iter()
+            .filter_map(|file| resolve_recorded_artifact(profile_dir, file))
+    }
+}
+
+/// Resolves an artifact recorded relative to the profile directory, returning `None` if it is not
+/// inside of it, either lexically or through symbolic links.
+fn resolve_recorded_artifact(profile_dir: &Utf8Path, file: &Utf8Path) -> Option<Utf8PathBuf> {
+    if !file
+        .components()
+        .all(|c| matches!(c, Utf8Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = profile_dir.join(file);
+    if let (Ok(dir), Ok(resolved)) = (profile_dir.canonicalize_utf8(), path.canonicalize_utf8()) {
+        if !resolved.starts_with(dir) {
+            return None;
+        }
+    }
+
+    Some(path)
+}
+
+/// Returns the directory where the state of a unit is kept between builds.
+pub fn unit_state_dir(profile_dir: &Filesystem, unit_name: &str) -> Filesystem {
+    profile_dir.child(STATE_DIR).child(unit_name)
+}
+
+/// Checks that the artifacts recorded for the last build of a unit are all still present.
+pub fn recorded_artifacts_exist(profile_dir: &Filesystem, unit_name: &str) -> bool {
+    let state_dir = unit_state_dir(profile_dir, unit_name);
+
+    ArtifactsRecord::try_read(&state_dir.path_unchecked().join(ARTIFACTS_RECORD_FILE)).is_some_and(
+        |record| {
+            record.files.iter().all(|file| {
+                resolve_recorded_artifact(profile_dir.path_unchecked(), file)
+                    .is_some_and(|path| path.is_file())
+            })
+        },
+    )
+}
+
+/// Returns the artifacts recorded for the last build of a unit, empty if none were recorded.
+pub fn recorded_artifacts(profile_dir: &Filesystem, unit_name: &str) -> Vec<Utf8PathBuf> {
+    let state_dir = unit_state_dir(profile_dir, unit_name);
+
+    ArtifactsRecord::read(&state_dir.path_unchecked().join(ARTIFACTS_RECORD_FILE))
+        .resolved_files(profile_dir.path_unchecked())
+        .collect()
+}
+
+/// An artifact file opened for writing.
+#[derive(Debug)]
+pub enum ArtifactFile {
+    /// A file of a Scarb workspace, locked while it's written.
+    Locked(FileLockGuard),
+    Unlocked(File),
+}
+
+impl Deref for ArtifactFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        match self {
+            Self::Locked(file) => file,
+            Self::Unlocked(file) => file,
+        }
+    }
+}
+
+impl DerefMut for ArtifactFile {
+    fn deref_mut(&mut self) -> &mut File {
+        match self {
+            Self::Locked(file) => file,
+            Self::Unlocked(file) => file,
+        }
+    }
+}
+
+/// Creates the artifact files of a compilation unit, ensuring that two different items never
+/// resolve to the same file, in the unit or in the other units of the profile.
+#[derive(Debug)]
+pub struct ArtifactsWriter<'a> {
+    /// The output directory of the unit.
+    dir: Utf8PathBuf,
+    /// The target directory of the profile, where the unit state is kept.
+    profile_dir: Utf8PathBuf,
+    unit_name: String,
+    /// The config of the Scarb workspace, used to lock the files. Files are not locked outside
+    /// of a Scarb workspace.
+    config: Option<&'a Config>,
+    ui: Ui,
+    /// The artifacts already created, relative to `dir`, with the item they were created for.
+    owners: HashMap<Utf8PathBuf, String>,
+    /// The artifacts recorded by the other units of the profile, with the unit they belong to.
+    other_units: HashMap<Utf8PathBuf, String>,
+}
+
+impl<'a> ArtifactsWriter<'a> {
+    pub fn new(
+        dir: Filesystem,
+        profile_dir: Filesystem,
+        unit_name: &str,
+        config: &'a Config,
+    ) -> Self {
+        let mut writer = Self::unlocked(
+            dir.path_unchecked().to_path_buf(),
+            profile_dir.path_unchecked().to_path_buf(),
+            unit_name,
+            config.ui(),
+        );
+        writer.config = Some(config);
+        writer
+    }
+
+    /// Creates a writer for a project outside of a Scarb workspace, the files are not locked.
+    pub fn unlocked(dir: Utf8PathBuf, profile_dir: Utf8PathBuf, unit_name: &str, ui: Ui) -> Self {
+        let other_units = other_units_artifacts(&profile_dir, unit_name);
+
+        Self {
+            dir,
+            profile_dir,
+            unit_name: unit_name.to_string(),
+            config: None,
+            ui,
+            owners: HashMap::new(),
+            other_units,
+        }
+    }
+
+    /// Creates the artifact at `path`, relative to the output directory, for the given `owner`.
+    ///
+    /// Returns `None` if the artifact was already created for the same owner, and fails if it was
+    /// created for another one or belongs to another unit.
+    pub fn create(
+        &mut self,
+        path: &Utf8Path,
+        owner: &str,
+        description: &str,
+    ) -> Result<Option<ArtifactFile>> {
+        match self.owners.get(path) {
+            Some(existing) if existing == owner => return Ok(None),
+            Some(existing) => bail!(
+                "`{existing}` and `{owner}` both resolve to the artifact `{path}`, consider \
+                 renaming one of them"
+            ),
+            None => {}
+        }
+
+        let full_path = self.dir.join(path);
+        if let Some(other_unit) = self.other_units.get(&full_path) {
+            bail!(
+                "`{owner}` resolves to the artifact `{path}` of compilation unit `{other_unit}`, \
+                 consider changing the `output-dir` of one of them, or cleaning the target \
+                 directory if that unit was removed"
+            );
+        }
+
+        let file = self.open(&full_path, description)?;
+        self.owners.insert(path.to_path_buf(), owner.to_string());
+
+        Ok(Some(file))
+    }
+
+    /// Removes the artifacts of the previous build of the unit that were not produced by this
+    /// one, and records the current artifacts for the next build.
+    pub fn finish(self) -> Result<()> {
+        let record_path = self
+            .profile_dir
+            .join(STATE_DIR)
+            .join(&self.unit_name)
+            .join(ARTIFACTS_RECORD_FILE);
+
+        let previous = ArtifactsRecord::read(&record_path);
+        let current = ArtifactsRecord {
+            files: self
+                .owners
+                .keys()
+                .map(|path| self.relative_to_profile_dir(path))
+                .collect::<Result<_>>()?,
+        };
+
+        for stale in previous.files.difference(&current.files) {
+            let Some(path) = resolve_recorded_artifact(&self.profile_dir, stale) else {
+                self.ui.warn(format!(
+                    "not removing stale artifact `{stale}`, as it is outside of the target \
+                     directory: {}",
+                    self.profile_dir
+                ));
+                continue;
+            };
+
+            self.ui.verbose(format!("removing stale artifact: {path}"));
+            remove_artifact(&path, &self.profile_dir)?;
+        }
+
+        let mut file = self.open(&record_path, "artifacts record")?;
+        serde_json::to_writer_pretty(file.deref_mut(), &current)
+            .with_context(|| format!("failed to serialize artifacts record: {}", self.unit_name))?;
+
+        Ok(())
+    }
+
+    /// Returns the path of an artifact relative to the profile directory, which contains the output
+    /// directory.
+    fn relative_to_profile_dir(&self, path: &Utf8Path) -> Result<Utf8PathBuf> {
+        let path = self.dir.join(path);
+
+        match path.strip_prefix(&self.profile_dir) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => bail!(
+                "artifact `{path}` is outside of the target directory: {}",
+                self.profile_dir
+            ),
+        }
+    }
+
+    /// Opens a file for writing, creating its parent directories.
+    fn open(&self, path: &Utf8Path, description: &str) -> Result<ArtifactFile> {
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            bail!("invalid artifact path: `{path}`");
+        };
+
+        match self.config {
+            Some(config) => Ok(ArtifactFile::Locked(
+                Filesystem::new(dir.to_path_buf()).create_rw(file_name, description, config)?,
+            )),
+            None => {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create directory: {dir}"))?;
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {description}: {path}"))?;
+                Ok(ArtifactFile::Unlocked(file))
+            }
+        }
+    }
+}
+
+/// Returns the artifacts recorded by the units of a profile other than `unit_name`, with the unit
+/// they belong to.
+fn other_units_artifacts(profile_dir: &Utf8Path, unit_name: &str) -> HashMap<Utf8PathBuf, String> {
+    let mut artifacts = HashMap::new();
+
+    let Ok(entries) = profile_dir.join(STATE_DIR).read_dir_utf8() else {
+        return artifacts;
+    };
+
+    for entry in entries.flatten() {
+        let other_unit = entry.file_name();
+        if other_unit == unit_name {
+            continue;
+        }
+
+        let record = ArtifactsRecord::read(&entry.path().join(ARTIFACTS_RECORD_FILE));
+        for file in record.resolved_files(profile_dir) {
+            artifacts.insert(file, other_unit.to_string());
+        }
+    }
+
+    artifacts
+}
+
+/// Removes all the artifacts recorded by the demo compiler in a target directory, for all the
+/// profiles and units.
+pub fn clean_target_dir(target_dir: &Utf8Path, config: &Config) -> Result<()> {
+    if !target_dir.is_dir() {
+        return Ok(());
+    }
+
+    for profile_entry in target_dir.read_dir_utf8()? {
+        let profile_dir = profile_entry?.into_path();
+        let state_dir = profile_dir.join(STATE_DIR);
+
+        if !state_dir.is_dir() {
+            continue;
+        }
+
+        for unit_entry in state_dir.read_dir_utf8()? {
+            let record = ArtifactsRecord::read(&unit_entry?.path().join(ARTIFACTS_RECORD_FILE));
+
+            for file in &record.files {
+                let Some(path) = resolve_recorded_artifact(&profile_dir, file) else {
+                    config.ui().warn(format!(
+                        "not removing artifact `{file}`, as it is outside of the target \
+                         directory: {profile_dir}"
+                    ));
+                    continue;
+                };
+
+                config.ui().verbose(format!("removing artifact: {path}"));
+                remove_artifact(&path, &profile_dir)?;
+            }
+        }
+
+        fs::remove_dir_all(&state_dir)
+            .with_context(|| format!("failed to remove directory: {state_dir}"))?;
+    }
+
+    Ok(())
+}
+
+/// Removes an artifact file, and its parent directories up to `root` if they are left empty.
+fn remove_artifact(path: &Utf8Path, root: &Utf8Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("failed to remove artifact: {path}")),
+    }
+
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+
+    Ok(())
+}
+
+/// Returns the path of a contract artifact, as a directory tree mirroring the module path
+/// (e.g. `demo_code/contract.sierra.json` for `demo_code::contract`).
+///
+/// Using the tree ensures that two different module paths never map to the same file.
+pub fn contract_artifact_path(contract_full_path: &str, extension: &str) -> Utf8PathBuf {
+    let mut path: Utf8PathBuf = contract_full_path.split("::").collect();
+    path.set_extension(extension);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::{Utf8Path, Utf8PathBuf};
+    use scarb::flock::Filesystem;
+    use scarb_ui::{OutputFormat, Ui, Verbosity};
+
+    use super::{
+        contract_artifact_path, recorded_artifacts, ArtifactsWriter, ARTIFACTS_RECORD_FILE,
+        STATE_DIR,
+    };
+
+    #[test]
+    fn mirrors_module_path() {
+        assert_eq!(
+            contract_artifact_path("demo_code::contract", "sierra.json"),
+            "demo_code/contract.sierra.json"
+        );
+        assert_ne!(
+            contract_artifact_path("a::b_c", "sierra.json"),
+            contract_artifact_path("a_b::c", "sierra.json")
+        );
+    }
+
+    fn temp_profile_dir(name: &str) -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!("demo-artifacts-{name}-{}", std::process::id()))
+    }
+
+    fn writer(profile_dir: &Utf8Path, unit_name: &str) -> ArtifactsWriter<'static> {
+        ArtifactsWriter::unlocked(
+            profile_dir.to_path_buf(),
+            profile_dir.to_path_buf(),
+            unit_name,
+            Ui::new(Verbosity::Quiet, OutputFormat::Text),
+        )
+    }
+
+    #[test]
+    fn rejects_artifacts_of_different_owners() {
+        let dir = temp_profile_dir("owners");
+        let mut artifacts = writer(&dir, "demo");
+        let path = Utf8Path::new("demo/contract.sierra.json");
+
+        assert!(artifacts
+            .create(path, "demo::contract", "class file")
+            .unwrap()
+            .is_some());
+        assert!(artifacts
+            .create(path, "demo::contract", "class file")
+            .unwrap()
+            .is_none());
+
+        let err = artifacts
+            .create(path, "demo::other", "class file")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`demo::contract` and `demo::other` both resolve to the artifact"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_artifacts_of_other_units() {
+        let dir = temp_profile_dir("units");
+        let path = Utf8Path::new("demo/contract.sierra.json");
+
+        let mut artifacts = writer(&dir, "first");
+        assert!(artifacts
+            .create(path, "demo::contract", "class file")
+            .unwrap()
+            .is_some());
+        artifacts.finish().unwrap();
+
+        let err = writer(&dir, "second")
+            .create(path, "demo::contract", "class file")
+            .unwrap_err();
+        assert!(err.to_string().contains(
+            "`demo::contract` resolves to the artifact `demo/contract.sierra.json` of \
+             compilation unit `first`"
+        ));
+
+        // The unit can still overwrite its own artifacts.
+        assert!(writer(&dir, "first")
+            .create(path, "demo::contract", "class file")
+            .unwrap()
+            .is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn records_artifacts_relative_to_the_profile_dir() {
+        let dir = temp_profile_dir("relative");
+        let profile_dir = dir.join("dev");
+        let record_path = profile_dir
+            .join(STATE_DIR)
+            .join("unit")
+            .join(ARTIFACTS_RECORD_FILE);
+
+        let mut artifacts = writer(&profile_dir, "unit");
+        artifacts
+            .create(
+                Utf8Path::new("demo/contract.sierra.json"),
+                "demo::contract",
+                "class file",
+            )
+            .unwrap();
+        artifacts.finish().unwrap();
+
+        let record = fs::read_to_string(&record_path).unwrap();
+        assert!(record.contains("\"demo/contract.sierra.json\""));
+        assert!(!record.contains(dir.as_str()));
+        assert_eq!(
+            recorded_artifacts(&Filesystem::new(profile_dir.clone()), "unit"),
+            [profile_dir.join("demo/contract.sierra.json")]
+        );
+
+        // Files recorded outside of the profile directory are never removed.
+        let outside = dir.join("outside.json");
+        fs::write(&outside, "{}").unwrap();
+        let record = serde_json::json!({ "files": ["../outside.json", outside] });
+        fs::write(&record_path, record.to_string()).unwrap();
+
+        writer(&profile_dir, "unit").finish().unwrap();
+        assert!(outside.is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
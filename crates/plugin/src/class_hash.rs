@@ -0,0 +1,166 @@
+use std::io::{self, Write};
+
+use anyhow::{Context, Result};
+use cairo_lang_starknet_classes::abi;
+use cairo_lang_starknet_classes::contract_class::{ContractClass, ContractEntryPoint};
+use cairo_lang_starknet_classes::keccak::starknet_keccak;
+use serde::Serialize;
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Poseidon, StarkHash};
+
+/// Computes the Sierra class hash of a contract class, as done by the Starknet sequencer.
+pub fn compute_class_hash(class: &ContractClass) -> Result<Felt> {
+    let entry_points = &class.entry_points_by_type;
+
+    let abi_str = abi_to_pythonic_string(class.abi.clone().unwrap_or_default())?;
+    let sierra_program = class
+        .sierra_program
+        .iter()
+        .map(|f| Felt::from(&f.value))
+        .collect::<Vec<_>>();
+
+    Ok(Poseidon::hash_array(&[
+        Felt::from_bytes_be_slice(
+            format!("CONTRACT_CLASS_V{}", class.contract_class_version).as_bytes(),
+        ),
+        entry_points_hash(&entry_points.external),
+        entry_points_hash(&entry_points.l1_handler),
+        entry_points_hash(&entry_points.constructor),
+        Felt::from(&starknet_keccak(abi_str.as_bytes())),
+        Poseidon::hash_array(&sierra_program),
+    ]))
+}
+
+/// Returns the hash of a set of entry points.
+fn entry_points_hash(entry_points: &[ContractEntryPoint]) -> Felt {
+    let elements = entry_points
+        .iter()
+        .flat_map(|ep| [Felt::from(&ep.selector), Felt::from(ep.function_idx)])
+        .collect::<Vec<_>>();
+
+    Poseidon::hash_array(&elements)
+}
+
+/// Serializes the ABI the same way the declare transaction does, which is a JSON
+/// with Python's default separators (`", "` and `": "`) and non-ASCII characters escaped.
+fn abi_to_pythonic_string(abi: abi::Contract) -> Result<String> {
+    let mut buffer = vec![];
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, PythonicFormatter);
+    abi.serialize(&mut serializer)
+        .context("failed to serialize contract ABI")?;
+
+    String::from_utf8(buffer).context("contract ABI is not valid UTF-8")
+}
+
+struct PythonicFormatter;
+
+impl serde_json::ser::Formatter for PythonicFormatter {
+    fn begin_array_value<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_value<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        writer.write_all(b": ")
+    }
+
+    /// Escapes non-ASCII characters as `\uXXXX`, using surrogate pairs outside of the BMP, as
+    /// Python's `json.dumps` does by default.
+    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut start = 0;
+        let mut buf = [0u16; 2];
+
+        for (i, c) in fragment.char_indices() {
+            if c.is_ascii() {
+                continue;
+            }
+
+            writer.write_all(fragment[start..i].as_bytes())?;
+            for unit in c.encode_utf16(&mut buf) {
+                write!(writer, "\\u{unit:04x}")?;
+            }
+            start = i + c.len_utf8();
+        }
+
+        writer.write_all(fragment[start..].as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo_lang_starknet_classes::contract_class::ContractClass;
+    use starknet_types_core::felt::Felt;
+
+    use super::{abi_to_pythonic_string, compute_class_hash};
+
+    /// A class with a non-ASCII ABI, its hash was computed with an implementation of the
+    /// `cairo-lang` Python class hash checked against the Poseidon and Keccak test vectors.
+    const CLASS: &str = r#"{
+        "sierra_program": ["0x1", "0x2", "0x3", "0x4"],
+        "sierra_program_debug_info": null,
+        "contract_class_version": "0.1.0",
+        "entry_points_by_type": {
+            "EXTERNAL": [
+                {
+                    "selector": "0x83afd3f4caedc6eebf44246fe54e38c95e3179a5ec9ea81740eca5b482d12e",
+                    "function_idx": 0
+                }
+            ],
+            "L1_HANDLER": [],
+            "CONSTRUCTOR": []
+        },
+        "abi": [
+            {
+                "type": "function",
+                "name": "salut_été",
+                "inputs": [{ "name": "montant", "type": "core::felt252" }],
+                "outputs": [{ "type": "core::felt252" }],
+                "state_mutability": "view"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn serializes_abi_as_python() {
+        let class: ContractClass = serde_json::from_str(CLASS).unwrap();
+
+        assert_eq!(
+            abi_to_pythonic_string(class.abi.unwrap()).unwrap(),
+            r#"[{"type": "function", "name": "salut_\u00e9t\u00e9", "inputs": [{"name": "montant", "type": "core::felt252"}], "outputs": [{"type": "core::felt252"}], "state_mutability": "view"}]"#
+        );
+    }
+
+    #[test]
+    fn computes_sierra_class_hash() {
+        let class: ContractClass = serde_json::from_str(CLASS).unwrap();
+
+        assert_eq!(
+            compute_class_hash(&class).unwrap(),
+            Felt::from_hex("0xaae4eb052579aaa8fb959b3f1a55582989ea2c01d0d3b12c61f98b4088d93c")
+                .unwrap()
+        );
+    }
+}
@@ -1,28 +1,109 @@
+use std::fs::File;
 use std::io::Write;
 use std::iter::zip;
 use std::ops::DerefMut;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use cairo_lang_compiler::db::RootDatabase;
-use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_compiler::CompilerConfig;
 use cairo_lang_filesystem::db::FilesGroup;
+use cairo_lang_filesystem::ids::CrateId;
 use cairo_lang_formatter::format_string;
 use cairo_lang_starknet::compile::compile_prepared_db;
-use cairo_lang_starknet::contract::find_contracts;
-use cairo_lang_utils::UpcastMut;
-use camino::Utf8Path;
+use cairo_lang_starknet::contract::{find_contracts, ContractDeclaration};
+use cairo_lang_starknet_classes::allowed_libfuncs::{AllowedLibfuncsError, ListSelector};
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use cairo_lang_starknet_classes::contract_class::ContractClass;
+use cairo_lang_utils::{Upcast, UpcastMut};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use scarb::compiler::helpers::{build_compiler_config, collect_main_crate_ids};
-use scarb::compiler::{CairoCompilationUnit, Compiler};
+use scarb::compiler::{CairoCompilationUnit, CompilationUnitAttributes, Compiler};
 use scarb::core::{TargetKind, Workspace};
+use scarb_ui::Ui;
+use serde::{Deserialize, Serialize};
+
+use crate::artifacts::{contract_artifact_path, ArtifactsWriter};
+use crate::class_hash::compute_class_hash;
+use crate::expansion::contract_generated_files;
+use crate::manifest::{
+    module_source_files, ContractManifest, DemoManifest, EntryPoints, MANIFEST_FILE_EXTENSION,
+};
+use crate::source_map::{build_source_map, GENERATED_SOURCE_EXTENSION, SOURCE_MAP_EXTENSION};
 
 pub const SOURCES_DIR: &str = "src";
+pub const DEMO_TARGET_KIND: &str = "demo";
+
+/// Options of the `[[target.demo]]` section of the manifest.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Props {
+    /// Writes each Sierra class, in `{module/path}.sierra.json`.
+    pub sierra: bool,
+    /// Also compiles each class to CASM, in `{module/path}.casm.json`.
+    pub casm: bool,
+    /// Writes the files generated by the demo plugin in the `src` directory, formatted, along with
+    /// the unformatted file and the source map referring to it.
+    pub expanded_sources: bool,
+    /// Pretty prints the JSON artifacts.
+    pub pretty_json: bool,
+    /// Directory where the artifacts are written, relative to the target directory of the
+    /// profile.
+    pub output_dir: Option<Utf8PathBuf>,
+    /// Fails the compilation if a class uses a libfunc that is not in the allowed list.
+    pub allowed_libfuncs: bool,
+    /// The allowed libfuncs list to validate the classes against, defaults to the audited list.
+    pub allowed_libfuncs_list: Option<SerdeListSelector>,
+}
+
+impl Default for Props {
+    fn default() -> Self {
+        Self {
+            sierra: true,
+            casm: false,
+            expanded_sources: true,
+            pretty_json: true,
+            output_dir: None,
+            allowed_libfuncs: false,
+            allowed_libfuncs_list: None,
+        }
+    }
+}
+
+impl Props {
+    /// Checks the options that can't be validated by deserialization only.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(output_dir) = &self.output_dir {
+            if output_dir.is_absolute()
+                || output_dir
+                    .components()
+                    .any(|c| matches!(c, Utf8Component::ParentDir))
+            {
+                bail!(
+                    "`output-dir` must be a relative path inside the target directory, found \
+                     `{output_dir}`"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Selects an allowed libfuncs list either by name (`audited`, `experimental`, `all`) or by
+/// path to a JSON file, relative to the package root.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SerdeListSelector {
+    Name { name: String },
+    Path { path: Utf8PathBuf },
+}
 
 #[derive(Debug)]
 pub struct DemoCompiler;
 
 impl Compiler for DemoCompiler {
     fn target_kind(&self) -> TargetKind {
-        TargetKind::new("demo")
+        TargetKind::new(DEMO_TARGET_KIND)
     }
 
     fn compile(
@@ -31,50 +112,292 @@ impl Compiler for DemoCompiler {
         db: &mut RootDatabase,
         ws: &Workspace<'_>,
     ) -> Result<()> {
-        let target_dir = unit.target_dir(ws);
-        let sources_dir = target_dir.child(Utf8Path::new(SOURCES_DIR));
+        let props: Props = unit
+            .main_component()
+            .target_props()
+            .and_then(|props: Props| props.validate().map(|_| props))
+            .with_context(|| {
+                format!(
+                    "invalid `[[target.demo]]` options for unit `{}`",
+                    unit.name()
+                )
+            })?;
+
+        let target_dir = match &props.output_dir {
+            Some(output_dir) => unit.target_dir(ws).child(output_dir),
+            None => unit.target_dir(ws),
+        };
 
         let main_crate_ids = collect_main_crate_ids(&unit, db);
         let compiler_config = build_compiler_config(db, &unit, &main_crate_ids, ws);
 
-        let main_crate_ids = collect_main_crate_ids(&unit, db);
+        let demo_unit = DemoUnit {
+            name: unit.name(),
+            props,
+            root: unit.main_component().package.root().to_path_buf(),
+            ui: ws.config().ui(),
+        };
 
-        let contracts = find_contracts(db.upcast_mut(), &main_crate_ids);
+        let mut artifacts = ArtifactsWriter::new(
+            target_dir,
+            unit.target_dir(ws),
+            &demo_unit.name,
+            ws.config(),
+        );
 
-        let contracts = contracts.iter().collect::<Vec<_>>();
+        compile_contracts(
+            db,
+            &main_crate_ids,
+            compiler_config,
+            &demo_unit,
+            &mut artifacts,
+        )?;
 
-        let classes = { compile_prepared_db(db, &contracts, compiler_config)? };
+        artifacts.finish()
+    }
+}
 
-        for (decl, class) in zip(contracts, classes) {
-            let contract_full_path = decl.module_id().full_path(db.upcast_mut());
+/// A unit compiled by the demo target, in a Scarb workspace or not.
+#[derive(Debug)]
+pub struct DemoUnit {
+    /// The name of the unit, used to name its manifest and state.
+    pub name: String,
+    pub props: Props,
+    /// The root directory of the package, the paths of the options are relative to it.
+    pub root: Utf8PathBuf,
+    pub ui: Ui,
+}
+
+/// Compiles the contracts of the main crates, and writes their artifacts along with the manifest
+/// of the unit.
+pub fn compile_contracts(
+    db: &mut RootDatabase,
+    main_crate_ids: &[CrateId],
+    compiler_config: CompilerConfig<'_>,
+    unit: &DemoUnit,
+    artifacts: &mut ArtifactsWriter<'_>,
+) -> Result<()> {
+    let props = &unit.props;
+
+    if !props.sierra && !props.casm {
+        unit.ui.warn(
+            "both Sierra and CASM outputs of the demo target have been disabled, only the \
+             manifest will be produced",
+        );
+    }
+
+    let contracts = find_contracts(db.upcast_mut(), main_crate_ids);
+
+    let contracts = contracts.iter().collect::<Vec<_>>();
+
+    let classes = { compile_prepared_db(db, &contracts, compiler_config)? };
+
+    if props.allowed_libfuncs {
+        check_allowed_libfuncs(unit, db, &contracts, &classes)?;
+    }
 
-            // save expanded contract source file
-            if let Ok(file_id) = db.module_main_file(decl.module_id()) {
-                if let Some(file_content) = db.file_content(file_id) {
-                    let src_file_name = format!("{contract_full_path}.cairo").replace("::", "_");
+    let mut manifest = DemoManifest {
+        unit: unit.name.clone(),
+        ..Default::default()
+    };
 
-                    let mut file =
-                        sources_dir.create_rw(src_file_name.clone(), "source file", ws.config())?;
-                    file.write(format_string(db, file_content.to_string()).as_bytes())
-                        .with_context(|| {
-                            format!("failed to serialize contract source: {contract_full_path}")
+    for (decl, class) in zip(contracts, classes) {
+        let contract_full_path = decl.module_id().full_path(db.upcast_mut());
+
+        // save the files expanded by the demo plugin, mirroring the module path
+        let mut expanded_files = vec![];
+        if props.expanded_sources {
+            for generated_file in contract_generated_files(db, decl.submodule_id) {
+                let path = Utf8Path::new(SOURCES_DIR).join(generated_file.relative_path(db));
+                let owner = generated_file.file_id.full_path(db.upcast());
+
+                let file_content = db
+                    .file_content(generated_file.file_id)
+                    .ok_or_else(|| anyhow!("failed to get expanded file content: {path}"))?
+                    .to_string();
+
+                if let Some(mut file) = artifacts.create(&path, &owner, "source file")? {
+                    file.write_all(format_string(db, file_content.clone()).as_bytes())
+                        .with_context(|| format!("failed to serialize expanded source: {path}"))?;
+                }
+
+                // The ranges of the source map refer to the file as produced by the plugin, which
+                // is written next to the formatted one.
+                if let Some(source_map) = build_source_map(db, generated_file.file_id) {
+                    let generated_path = path.with_extension(GENERATED_SOURCE_EXTENSION);
+                    let map_path = path.with_extension(SOURCE_MAP_EXTENSION);
+
+                    if let Some(mut file) =
+                        artifacts.create(&generated_path, &owner, "generated source file")?
+                    {
+                        file.write_all(file_content.as_bytes()).with_context(|| {
+                            format!("failed to serialize generated source: {generated_path}")
                         })?;
-                } else {
-                    return Err(anyhow!(
-                        "failed to get source file content: {contract_full_path}"
-                    ));
+                    }
+
+                    if let Some(mut file) =
+                        artifacts.create(&map_path, &owner, "source map file")?
+                    {
+                        write_json(file.deref_mut(), &source_map, props.pretty_json).with_context(
+                            || format!("failed to serialize source map: {map_path}"),
+                        )?;
+                    }
                 }
-            } else {
-                return Err(anyhow!("failed to get source file: {contract_full_path}"));
+
+                expanded_files.push(path);
             }
+        }
 
-            let file_name = format!("{contract_full_path}.sierra.json");
-            let mut file = target_dir.create_rw(file_name.clone(), "class file", ws.config())?;
-            serde_json::to_writer_pretty(file.deref_mut(), &class).with_context(|| {
-                format!("failed to serialize contract artifact: {contract_full_path}")
-            })?;
+        let sierra_file = if props.sierra {
+            let path = contract_artifact_path(&contract_full_path, "sierra.json");
+
+            if let Some(mut file) = artifacts.create(&path, &contract_full_path, "class file")? {
+                write_json(file.deref_mut(), &class, props.pretty_json).with_context(|| {
+                    format!("failed to serialize contract artifact: {contract_full_path}")
+                })?;
+            }
+
+            Some(path)
+        } else {
+            None
+        };
+
+        let class_hash = compute_class_hash(&class)
+            .with_context(|| format!("failed to compute class hash: {contract_full_path}"))?;
+
+        let (casm_file, compiled_class_hash) = if props.casm {
+            let casm_class =
+                CasmContractClass::from_contract_class(class.clone(), false, usize::MAX)
+                    .with_context(|| {
+                        format!("failed to compile contract to CASM: {contract_full_path}")
+                    })?;
+
+            let path = contract_artifact_path(&contract_full_path, "casm.json");
+
+            if let Some(mut file) = artifacts.create(&path, &contract_full_path, "class file")? {
+                write_json(file.deref_mut(), &casm_class, props.pretty_json).with_context(
+                    || format!("failed to serialize contract CASM artifact: {contract_full_path}"),
+                )?;
+            }
+
+            (Some(path), Some(casm_class.compiled_class_hash()))
+        } else {
+            (None, None)
+        };
+
+        let mut hashes = format!("{contract_full_path}\n  class hash: {class_hash:#x}");
+        if let Some(compiled_class_hash) = compiled_class_hash {
+            hashes.push_str(&format!(
+                "\n  compiled class hash: {compiled_class_hash:#x}"
+            ));
         }
+        unit.ui.print(hashes);
 
-        Ok(())
+        manifest.contracts.push(ContractManifest {
+            module_path: contract_full_path.clone(),
+            sierra_file,
+            casm_file,
+            expanded_files,
+            class_hash: Some(format!("{class_hash:#x}")),
+            compiled_class_hash: compiled_class_hash.map(|h| format!("{h:#x}")),
+            entry_points: EntryPoints::from_class(&class),
+            source_files: module_source_files(db, decl.module_id()),
+        });
+    }
+
+    let manifest_path = Utf8PathBuf::from(format!("{}.{MANIFEST_FILE_EXTENSION}", unit.name));
+    if let Some(mut file) = artifacts.create(&manifest_path, &unit.name, "manifest file")? {
+        write_json(file.deref_mut(), &manifest, props.pretty_json)
+            .with_context(|| format!("failed to serialize manifest: {}", unit.name))?;
+    }
+
+    Ok(())
+}
+
+/// Serializes a JSON artifact, pretty printed if requested.
+fn write_json<T: Serialize>(file: &mut File, value: &T, pretty: bool) -> serde_json::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(file, value)
+    } else {
+        serde_json::to_writer(file, value)
+    }
+}
+
+/// Checks that the classes only use libfuncs from the allowed list, the same way Scarb's
+/// `starknet-contract` target does.
+///
+/// Each disallowed libfunc is reported as an error naming the contract, failing the compilation.
+fn check_allowed_libfuncs(
+    unit: &DemoUnit,
+    db: &RootDatabase,
+    contracts: &[&ContractDeclaration],
+    classes: &[ContractClass],
+) -> Result<()> {
+    let props = &unit.props;
+    let list_selector = match &props.allowed_libfuncs_list {
+        Some(SerdeListSelector::Name { name }) => ListSelector::ListName(name.clone()),
+        Some(SerdeListSelector::Path { path }) => {
+            let path = unit.root.join(path);
+            ListSelector::ListFile(path.into_string())
+        }
+        None => ListSelector::default(),
+    };
+
+    let mut found_disallowed = false;
+
+    for (decl, class) in zip(contracts, classes) {
+        let contract_full_path = decl.module_id().full_path(db);
+
+        match class.validate_version_compatible(list_selector.clone()) {
+            Ok(()) => {}
+            Err(AllowedLibfuncsError::UnsupportedLibfunc {
+                invalid_libfunc,
+                allowed_libfuncs_list_name: _,
+            }) => {
+                let diagnostic = format!(
+                    "libfunc `{invalid_libfunc}` is not allowed in the libfuncs list \
+                     `{list_selector}`\n --> contract: {contract_full_path}"
+                );
+
+                found_disallowed = true;
+                unit.ui.error(diagnostic);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("failed to check allowed libfuncs for contract: {contract_full_path}")
+                });
+            }
+        }
+    }
+
+    if found_disallowed {
+        bail!("aborting compilation, because contracts use disallowed Sierra libfuncs");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Props;
+
+    fn props_with_output_dir(output_dir: &str) -> Props {
+        Props {
+            output_dir: Some(output_dir.into()),
+            ..Props::default()
+        }
+    }
+
+    #[test]
+    fn accepts_relative_output_dir() {
+        assert!(Props::default().validate().is_ok());
+        assert!(props_with_output_dir("contracts/out").validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_output_dir_outside_target_dir() {
+        assert!(props_with_output_dir("/tmp/out").validate().is_err());
+        assert!(props_with_output_dir("../out").validate().is_err());
+        assert!(props_with_output_dir("out/../../out").validate().is_err());
     }
 }
@@ -0,0 +1,101 @@
+use std::iter::zip;
+
+use cairo_lang_defs::db::{DefsGroup, GeneratedFileInfo};
+use cairo_lang_defs::ids::{FileIndex, LanguageElementId, ModuleFileId, ModuleId, SubmoduleId};
+use cairo_lang_filesystem::ids::FileId;
+use cairo_lang_utils::Upcast;
+use camino::Utf8PathBuf;
+
+use crate::plugin::DemoAuxData;
+
+/// A virtual file generated by the `BuiltinDemoPlugin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DemoGeneratedFile {
+    /// The module the file was generated in.
+    pub module_id: ModuleId,
+    /// The generated virtual file.
+    pub file_id: FileId,
+}
+
+impl DemoGeneratedFile {
+    /// The path of the file, mirroring the module path (e.g. `demo_code/contract.cairo`).
+    pub fn relative_path(&self, db: &dyn DefsGroup) -> Utf8PathBuf {
+        let mut path: Utf8PathBuf = self.module_id.full_path(db).split("::").collect();
+        path.push(format!("{}.cairo", self.file_id.file_name(db.upcast())));
+        path
+    }
+}
+
+/// Collects all the files generated by the `BuiltinDemoPlugin` for a contract.
+///
+/// This includes the files the contract module originates from, outermost first, and the files
+/// generated inside the contract module and its submodules.
+pub fn contract_generated_files(
+    db: &dyn DefsGroup,
+    submodule_id: SubmoduleId,
+) -> Vec<DemoGeneratedFile> {
+    let mut files = vec![];
+
+    // The contract module may have been rewritten by several plugins, each one generating a new
+    // file from the previous one, follow the origins up to the user's file.
+    let mut module_file_id = submodule_id.module_file_id(db);
+    loop {
+        let ModuleFileId(module_id, FileIndex(index)) = module_file_id;
+
+        let Ok(infos) = db.module_generated_file_infos(module_id) else {
+            break;
+        };
+        let Some(Some(info)) = infos.get(index) else {
+            break;
+        };
+
+        if is_demo_generated(info) {
+            if let Ok(file_id) = db.module_file(module_file_id) {
+                files.push(DemoGeneratedFile { module_id, file_id });
+            }
+        }
+
+        module_file_id = info.origin;
+    }
+
+    files.reverse();
+
+    collect_module_generated_files(db, ModuleId::Submodule(submodule_id), &mut files);
+
+    files
+}
+
+/// Recursively collects the files generated by the `BuiltinDemoPlugin` inside a module.
+fn collect_module_generated_files(
+    db: &dyn DefsGroup,
+    module_id: ModuleId,
+    files: &mut Vec<DemoGeneratedFile>,
+) {
+    if let (Ok(module_files), Ok(infos)) = (
+        db.module_files(module_id),
+        db.module_generated_file_infos(module_id),
+    ) {
+        for (file_id, info) in zip(module_files.iter(), infos.iter()) {
+            let file = DemoGeneratedFile {
+                module_id,
+                file_id: *file_id,
+            };
+
+            if info.as_ref().is_some_and(is_demo_generated) && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    if let Ok(submodules) = db.module_submodules_ids(module_id) {
+        for submodule_id in submodules.iter() {
+            collect_module_generated_files(db, ModuleId::Submodule(*submodule_id), files);
+        }
+    }
+}
+
+fn is_demo_generated(info: &GeneratedFileInfo) -> bool {
+    info.aux_data
+        .as_ref()
+        .is_some_and(|aux_data| aux_data.as_any().is::<DemoAuxData>())
+}
@@ -0,0 +1,144 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::{env, fs};
+
+use anyhow::{Context, Result};
+use cairo_lang_starknet_classes::compiler_version::current_compiler_version_id;
+use camino::Utf8Path;
+use scarb::compiler::{CairoCompilationUnit, CompilationUnitAttributes};
+use scarb::core::Workspace;
+use scarb::flock::Filesystem;
+
+use crate::artifacts::unit_state_dir;
+
+/// File, inside the state directory of a unit, storing the fingerprint of its last build.
+const FINGERPRINT_FILE: &str = "fingerprint";
+
+/// Files of a procedural macro package, next to its manifest, that change how it is built by Cargo.
+const PROC_MACRO_CARGO_FILES: &[&str] = &["Cargo.toml", "Cargo.lock", "build.rs"];
+
+/// Fingerprint of all the inputs of a compilation unit: sources, manifests, plugins, target
+/// options, compiler binary and profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint(String);
+
+impl Fingerprint {
+    pub fn compute(unit: &CairoCompilationUnit, ws: &Workspace<'_>) -> Result<Self> {
+        let mut hasher = DefaultHasher::new();
+
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        // The version of the demo plugin doesn't change when its code is edited, the binary
+        // embedding it does.
+        hash_current_exe(&mut hasher)?;
+        current_compiler_version_id().to_string().hash(&mut hasher);
+        ws.config().profile().as_str().hash(&mut hasher);
+
+        unit.main_component()
+            .target_kind()
+            .to_string()
+            .hash(&mut hasher);
+        format!("{:?}", unit.main_component().first_target().params).hash(&mut hasher);
+        format!("{:?}", unit.cfg_set).hash(&mut hasher);
+        format!("{:?}", unit.compiler_config).hash(&mut hasher);
+
+        for plugin in &unit.cairo_plugins {
+            plugin.package.id.to_string().hash(&mut hasher);
+
+            // Procedural macros may be local packages, edited along the Cairo code.
+            if !plugin.builtin {
+                let root = plugin.package.root();
+
+                hash_file(&mut hasher, plugin.package.manifest_path())?;
+                for file in PROC_MACRO_CARGO_FILES {
+                    let path = root.join(file);
+                    if path.is_file() {
+                        hash_file(&mut hasher, &path)?;
+                    }
+                }
+                hash_sources(&mut hasher, &root.join("src"), "rs")?;
+            }
+        }
+
+        for component in unit.components() {
+            component.package.id.to_string().hash(&mut hasher);
+            format!("{:?}", component.cfg_set).hash(&mut hasher);
+
+            let manifest_path = component.package.manifest_path();
+            hash_file(&mut hasher, manifest_path)?;
+
+            hash_sources(&mut hasher, component.first_target().source_root(), "cairo")?;
+        }
+
+        Ok(Self(format!("{:016x}", hasher.finish())))
+    }
+
+    /// Checks if the unit was already built with the same fingerprint.
+    pub fn is_fresh(&self, profile_dir: &Filesystem, unit_name: &str) -> bool {
+        let path = unit_state_dir(profile_dir, unit_name)
+            .path_unchecked()
+            .join(FINGERPRINT_FILE);
+
+        fs::read_to_string(path).is_ok_and(|previous| previous == self.0)
+    }
+
+    /// Stores the fingerprint of a successful build of the unit.
+    pub fn store(&self, profile_dir: &Filesystem, unit_name: &str) -> Result<()> {
+        let state_dir = unit_state_dir(profile_dir, unit_name);
+        let path = state_dir.path_existent()?.join(FINGERPRINT_FILE);
+
+        fs::write(&path, &self.0).with_context(|| format!("failed to write fingerprint: {path}"))
+    }
+
+    /// Removes the fingerprint of a unit, forcing its next build.
+    pub fn invalidate(profile_dir: &Filesystem, unit_name: &str) {
+        let path = unit_state_dir(profile_dir, unit_name)
+            .path_unchecked()
+            .join(FINGERPRINT_FILE);
+
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Hashes the path, size and modification time of the running binary, which change whenever it is
+/// rebuilt.
+fn hash_current_exe(hasher: &mut DefaultHasher) -> Result<()> {
+    let path = env::current_exe().context("failed to get the path of the current executable")?;
+    let metadata = fs::metadata(&path)
+        .with_context(|| format!("failed to read metadata: {}", path.display()))?;
+
+    path.hash(hasher);
+    metadata.len().hash(hasher);
+    metadata.modified().ok().hash(hasher);
+    Ok(())
+}
+
+fn hash_file(hasher: &mut DefaultHasher, path: &Utf8Path) -> Result<()> {
+    let content = fs::read(path).with_context(|| format!("failed to read file: {path}"))?;
+    path.hash(hasher);
+    content.hash(hasher);
+    Ok(())
+}
+
+/// Hashes all the files with the given extension under `dir`, in a deterministic order.
+fn hash_sources(hasher: &mut DefaultHasher, dir: &Utf8Path, extension: &str) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut entries = dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read directory: {dir}"))?
+        .map(|entry| entry.map(|e| e.into_path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            hash_sources(hasher, &path, extension)?;
+        } else if path.extension() == Some(extension) {
+            hash_file(hasher, &path)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_compiler::diagnostics::DiagnosticsReporter;
+use cairo_lang_compiler::project::{
+    get_main_crate_ids_from_project, ProjectConfig, ProjectConfigContent,
+};
+use cairo_lang_compiler::CompilerConfig;
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_diagnostics::{DiagnosticEntry, Diagnostics, Severity};
+use cairo_lang_filesystem::db::{
+    CrateSettings, Edition, ExperimentalFeaturesConfig, FilesGroup, FilesGroupEx,
+};
+use cairo_lang_filesystem::detect::detect_corelib;
+use cairo_lang_filesystem::ids::{CrateId, Directory, FileId};
+use cairo_lang_lowering::db::LoweringGroup;
+use cairo_lang_parser::db::ParserGroup;
+use cairo_lang_project::AllCratesConfig;
+use cairo_lang_semantic::db::SemanticGroup;
+use cairo_lang_starknet::compile::compile_prepared_db;
+use cairo_lang_starknet::contract::find_contracts;
+use cairo_lang_starknet::starknet_plugin_suite;
+use cairo_lang_starknet_classes::contract_class::ContractClass;
+use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+use cairo_lang_utils::Upcast;
+use camino::Utf8PathBuf;
+use smol_str::SmolStr;
+
+use crate::expansion::contract_generated_files;
+use crate::plugin::demo_plugin_suite;
+use crate::source_map::{range_in_file, Range};
+
+/// The directory the in memory crate is rooted at. It doesn't exist, the content of the files is
+/// provided to the database.
+const VIRTUAL_ROOT: &str = "/__demo_in_memory__";
+
+/// The result of an in memory compilation.
+#[derive(Debug)]
+pub struct InMemoryCompilation {
+    /// The files generated by the demo plugin, for each contract.
+    pub expanded_files: Vec<ExpandedFile>,
+    /// The diagnostics of the crate, located in the original files when possible.
+    pub diagnostics: Vec<LocatedDiagnostic>,
+    /// The compiled classes with the full path of their contract module, only compiled if there
+    /// are no errors.
+    pub classes: Vec<(String, ContractClass)>,
+}
+
+impl InMemoryCompilation {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+}
+
+/// A file generated by the demo plugin.
+#[derive(Debug)]
+pub struct ExpandedFile {
+    /// The path of the file, mirroring the module path (e.g. `demo/contract.cairo`).
+    pub path: Utf8PathBuf,
+    /// The content of the file, unformatted.
+    pub content: String,
+}
+
+#[derive(Debug)]
+pub struct LocatedDiagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<DiagnosticLocation>,
+}
+
+#[derive(Debug)]
+pub struct DiagnosticLocation {
+    /// The path of the file as given to [`compile_in_memory`], or the full path of the file if
+    /// the diagnostic is located outside of them.
+    pub file: String,
+    pub range: Range,
+}
+
+/// Compiles a crate with the demo plugin, without reading or writing any file except the
+/// corelib, which is detected the same way the Cairo compiler does.
+///
+/// `files` maps the paths of the crate files, relative to the crate root (e.g. `lib.cairo`), to
+/// their content.
+pub fn compile_in_memory(
+    crate_name: &str,
+    files: &BTreeMap<Utf8PathBuf, String>,
+) -> Result<InMemoryCompilation> {
+    let crate_root = PathBuf::from(VIRTUAL_ROOT).join("src");
+
+    let corelib = detect_corelib().context("failed to find the Cairo corelib")?;
+
+    let project_config = ProjectConfig {
+        base_path: VIRTUAL_ROOT.into(),
+        corelib: Some(Directory::Real(corelib)),
+        content: ProjectConfigContent {
+            crate_roots: OrderedHashMap::from([(SmolStr::new(crate_name), crate_root.clone())]),
+            crates_config: AllCratesConfig {
+                global: CrateSettings {
+                    edition: Edition::V2024_07,
+                    version: None,
+                    cfg_set: None,
+                    experimental_features: ExperimentalFeaturesConfig::default(),
+                },
+                ..Default::default()
+            },
+        },
+    };
+
+    let mut db = RootDatabase::builder()
+        .with_project_config(project_config.clone())
+        .with_plugin_suite(demo_plugin_suite())
+        .with_plugin_suite(starknet_plugin_suite())
+        .build()?;
+
+    let mut input_files = HashMap::new();
+    for (path, content) in files {
+        let file_id = FileId::new(&db, crate_root.join(path));
+        db.override_file_content(file_id, Some(Arc::from(content.as_str())));
+        input_files.insert(file_id, path.to_string());
+    }
+
+    let main_crate_ids = get_main_crate_ids_from_project(&mut db, &project_config);
+
+    let diagnostics = collect_diagnostics(&db, &main_crate_ids, &input_files);
+
+    let contracts = find_contracts(&db, &main_crate_ids);
+
+    let mut expanded_files = vec![];
+    for decl in &contracts {
+        for generated_file in contract_generated_files(&db, decl.submodule_id) {
+            if let Some(content) = db.file_content(generated_file.file_id) {
+                expanded_files.push(ExpandedFile {
+                    path: generated_file.relative_path(&db),
+                    content: content.to_string(),
+                });
+            }
+        }
+    }
+
+    let has_errors = diagnostics.iter().any(|d| d.severity == Severity::Error);
+    let classes = if has_errors {
+        vec![]
+    } else {
+        let contracts = contracts.iter().collect::<Vec<_>>();
+        let compiler_config = CompilerConfig {
+            diagnostics_reporter: DiagnosticsReporter::ignoring().allow_warnings(),
+            replace_ids: true,
+            ..CompilerConfig::default()
+        };
+
+        let classes = compile_prepared_db(&db, &contracts, compiler_config)?;
+
+        contracts
+            .iter()
+            .map(|decl| decl.module_id().full_path(&db))
+            .zip(classes)
+            .collect()
+    };
+
+    Ok(InMemoryCompilation {
+        expanded_files,
+        diagnostics,
+        classes,
+    })
+}
+
+/// Collects the parser, semantic and lowering diagnostics of the crates, the same way the
+/// diagnostics reporter does.
+fn collect_diagnostics(
+    db: &RootDatabase,
+    crate_ids: &[CrateId],
+    input_files: &HashMap<FileId, String>,
+) -> Vec<LocatedDiagnostic> {
+    let mut diagnostics = vec![];
+    let mut processed_file_ids = HashSet::new();
+
+    for crate_id in crate_ids {
+        for module_id in db.crate_modules(*crate_id).iter() {
+            for file_id in db.module_files(*module_id).unwrap_or_default().iter() {
+                if processed_file_ids.insert(*file_id) {
+                    let files_db: &dyn FilesGroup = db;
+                    collect_group(
+                        db,
+                        files_db,
+                        db.file_syntax_diagnostics(*file_id),
+                        input_files,
+                        &mut diagnostics,
+                    );
+                }
+            }
+
+            if let Ok(group) = db.module_semantic_diagnostics(*module_id) {
+                let semantic_db: &dyn SemanticGroup = db;
+                collect_group(db, semantic_db, group, input_files, &mut diagnostics);
+            }
+
+            if let Ok(group) = db.module_lowering_diagnostics(*module_id) {
+                let lowering_db: &dyn LoweringGroup = db;
+                collect_group(db, lowering_db, group, input_files, &mut diagnostics);
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn collect_group<TEntry: DiagnosticEntry>(
+    db: &RootDatabase,
+    entry_db: &TEntry::DbType,
+    group: Diagnostics<TEntry>,
+    input_files: &HashMap<FileId, String>,
+    diagnostics: &mut Vec<LocatedDiagnostic>,
+) {
+    let defs_db: &dyn DefsGroup = db;
+
+    for entry in group.get_all() {
+        // Diagnostics in plugin generated files are mapped back to the user's code.
+        let location = entry.location(entry_db).user_location(db.upcast());
+
+        let location = range_in_file(defs_db, location.file_id, location.span).map(|range| {
+            DiagnosticLocation {
+                file: input_files
+                    .get(&location.file_id)
+                    .cloned()
+                    .unwrap_or_else(|| location.file_id.full_path(db.upcast())),
+                range,
+            }
+        });
+
+        diagnostics.push(LocatedDiagnostic {
+            severity: entry.severity(),
+            message: entry.format(entry_db),
+            location,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use cairo_lang_diagnostics::Severity;
+    use camino::Utf8PathBuf;
+
+    use super::compile_in_memory;
+
+    fn contract(body: &str) -> BTreeMap<Utf8PathBuf, String> {
+        let code = format!(
+            "#[starknet::interface]
+trait ICounter<T> {{
+    fn get(self: @T) -> felt252;
+}}
+
+#[custom::contract]
+mod counter {{
+    #[storage]
+    struct Storage {{}}
+
+    #[abi(embed_v0)]
+    impl CounterImpl of super::ICounter<ContractState> {{
+        fn get() -> felt252 {{
+{body}
+        }}
+    }}
+}}
+"
+        );
+
+        BTreeMap::from([(Utf8PathBuf::from("lib.cairo"), code)])
+    }
+
+    #[test]
+    #[ignore = "requires the Cairo corelib, run with `cargo test -- --ignored`"]
+    fn compiles_contract() {
+        let compilation = compile_in_memory("demo", &contract("            42")).unwrap();
+
+        assert!(!compilation.has_errors(), "{:?}", compilation.diagnostics);
+
+        assert_eq!(compilation.expanded_files.len(), 1);
+        assert_eq!(compilation.expanded_files[0].path, "demo/counter.cairo");
+        assert!(compilation.expanded_files[0]
+            .content
+            .contains("fn get(self: @ContractState)"));
+
+        assert_eq!(compilation.classes.len(), 1);
+        assert_eq!(compilation.classes[0].0, "demo::counter");
+    }
+
+    #[test]
+    #[ignore = "requires the Cairo corelib, run with `cargo test -- --ignored`"]
+    fn maps_plugin_diagnostics_to_the_original_file() {
+        let compilation = compile_in_memory(
+            "demo",
+            &contract("            let fail = 1;\n            fail"),
+        )
+        .unwrap();
+
+        let diagnostic = compilation
+            .diagnostics
+            .iter()
+            .find(|d| {
+                d.message
+                    .contains("Invalid statement starting with 'let fail = 1'")
+            })
+            .expect("the plugin diagnostic must be reported");
+
+        assert_eq!(diagnostic.severity, Severity::Error);
+
+        let location = diagnostic.location.as_ref().unwrap();
+        assert_eq!(location.file, "lib.cairo");
+        assert_eq!(location.range.start.line, 13);
+        assert_eq!(location.range.start.col, 12);
+
+        assert!(compilation.classes.is_empty());
+    }
+}
@@ -1,4 +1,12 @@
+pub mod artifacts;
+pub mod class_hash;
 pub mod compiler;
+pub mod expansion;
+pub mod fingerprint;
+pub mod in_memory;
+pub mod manifest;
 pub mod plugin;
+pub mod project;
 pub mod scarb_funcs;
-mod syntax_utils;
+pub mod source_map;
+pub mod version;
@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_defs::ids::ModuleId;
+use cairo_lang_filesystem::ids::{FileId, FileLongId};
+use cairo_lang_starknet_classes::abi;
+use cairo_lang_starknet_classes::contract_class::ContractClass;
+use cairo_lang_starknet_classes::keccak::starknet_keccak;
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+
+/// Extension of the manifest written at the root of the output directory of each unit, named after
+/// the unit (`{unit}.demo_manifest.json`) as the units of a profile may share the same directory.
+pub const MANIFEST_FILE_EXTENSION: &str = "demo_manifest.json";
+
+/// Index of all the artifacts produced by the `DemoCompiler` for a compilation unit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DemoManifest {
+    /// The name of the compilation unit.
+    pub unit: String,
+    /// The contracts found in the compilation unit.
+    pub contracts: Vec<ContractManifest>,
+}
+
+/// Artifacts and metadata of a single contract.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractManifest {
+    /// The full module path of the contract (e.g. `demo_code::contract`).
+    pub module_path: String,
+    /// The Sierra class file name, relative to the output directory, if Sierra is enabled.
+    pub sierra_file: Option<Utf8PathBuf>,
+    /// The CASM class file name, relative to the output directory, if CASM is enabled.
+    pub casm_file: Option<Utf8PathBuf>,
+    /// The files generated by the demo plugin for the contract, relative to the output directory.
+    pub expanded_files: Vec<Utf8PathBuf>,
+    /// The Sierra class hash, if computed.
+    pub class_hash: Option<String>,
+    /// The CASM compiled class hash, if computed.
+    pub compiled_class_hash: Option<String>,
+    /// The entry points exposed by the contract ABI.
+    pub entry_points: EntryPoints,
+    /// The original source files (on disk) the contract is built from.
+    pub source_files: Vec<Utf8PathBuf>,
+}
+
+/// Entry points of a contract, grouped by type.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EntryPoints {
+    pub external: Vec<EntryPoint>,
+    pub l1_handler: Vec<EntryPoint>,
+    pub constructor: Vec<EntryPoint>,
+}
+
+/// An entry point of a contract.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntryPoint {
+    /// The name of the function, as found in the ABI.
+    pub name: String,
+    /// The selector of the function, as a hex string.
+    pub selector: String,
+}
+
+impl EntryPoint {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            selector: format!("{:#x}", starknet_keccak(name.as_bytes())),
+        }
+    }
+}
+
+impl EntryPoints {
+    /// Extracts the entry points from the ABI of the given class.
+    pub fn from_class(class: &ContractClass) -> Self {
+        let mut entry_points = Self::default();
+
+        let Some(abi) = &class.abi else {
+            return entry_points;
+        };
+
+        let items = abi.clone().into_iter().collect::<Vec<_>>();
+
+        for item in &items {
+            match item {
+                abi::Item::Function(f) => entry_points.external.push(EntryPoint::new(&f.name)),
+                abi::Item::L1Handler(f) => entry_points.l1_handler.push(EntryPoint::new(&f.name)),
+                abi::Item::Constructor(f) => {
+                    entry_points.constructor.push(EntryPoint::new(&f.name))
+                }
+                abi::Item::Impl(imp) => {
+                    // Functions of embedded impls are declared in the interface item.
+                    let interface_fns = items.iter().find_map(|i| match i {
+                        abi::Item::Interface(interface) if interface.name == imp.interface_name => {
+                            Some(&interface.items)
+                        }
+                        _ => None,
+                    });
+
+                    for f in interface_fns.into_iter().flatten() {
+                        if let abi::Item::Function(f) = f {
+                            entry_points.external.push(EntryPoint::new(&f.name));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        entry_points
+    }
+}
+
+/// Collects the files on disk a module is built from.
+///
+/// Plugin generated files are resolved to the on disk file they originate from.
+pub fn module_source_files(db: &dyn DefsGroup, module_id: ModuleId) -> Vec<Utf8PathBuf> {
+    let mut source_files = vec![];
+
+    let Ok(files) = db.module_files(module_id) else {
+        return source_files;
+    };
+
+    for file_id in files.iter() {
+        if let Some(path) = original_file_path(db, *file_id) {
+            if let Ok(path) = Utf8PathBuf::from_path_buf(path) {
+                if !source_files.contains(&path) {
+                    source_files.push(path);
+                }
+            }
+        }
+    }
+
+    source_files
+}
+
+/// Walks up the virtual files parents until a file on disk is found.
+fn original_file_path(db: &dyn DefsGroup, file_id: FileId) -> Option<PathBuf> {
+    match db.lookup_intern_file(file_id) {
+        FileLongId::OnDisk(path) => Some(path),
+        FileLongId::Virtual(vf) => vf.parent.and_then(|p| original_file_path(db, p)),
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_compiler::diagnostics::DiagnosticsReporter;
+use cairo_lang_compiler::project::{get_main_crate_ids_from_project, ProjectConfig};
+use cairo_lang_compiler::CompilerConfig;
+use cairo_lang_diagnostics::{FormattedDiagnosticEntry, Severity};
+use cairo_lang_starknet::starknet_plugin_suite;
+use camino::Utf8Path;
+use scarb_ui::Ui;
+
+use crate::artifacts::ArtifactsWriter;
+use crate::compiler::{compile_contracts, DemoUnit, Props};
+use crate::plugin::demo_plugin_suite;
+
+/// The profile directory the artifacts are written to, as Scarb does for the default profile.
+const PROFILE_DIR: &str = "dev";
+
+/// Compiles the Cairo project described by the `cairo_project.toml` file of `project_dir`, without
+/// Scarb, and writes the same artifacts as the demo target in `target_dir`.
+///
+/// The demo plugin is enabled for all the crates of the project, and the unit is named after the
+/// project directory.
+pub fn compile_cairo_project(
+    project_dir: &Utf8Path,
+    target_dir: &Utf8Path,
+    props: Props,
+    ui: Ui,
+) -> Result<()> {
+    props.validate()?;
+
+    let project_config = ProjectConfig::from_directory(project_dir.as_std_path())
+        .with_context(|| format!("failed to read Cairo project: {project_dir}"))?;
+
+    let mut db = RootDatabase::builder()
+        .detect_corelib()
+        .with_project_config(project_config.clone())
+        .with_plugin_suite(demo_plugin_suite())
+        .with_plugin_suite(starknet_plugin_suite())
+        .build()?;
+
+    let main_crate_ids = get_main_crate_ids_from_project(&mut db, &project_config);
+
+    let reporter_ui = ui.clone();
+    let compiler_config = CompilerConfig {
+        diagnostics_reporter: DiagnosticsReporter::callback(
+            move |entry: FormattedDiagnosticEntry| {
+                let message = entry.message().trim_end();
+                match entry.severity() {
+                    Severity::Error => reporter_ui.error(message),
+                    Severity::Warning => reporter_ui.warn(message),
+                }
+            },
+        )
+        .with_crates(&main_crate_ids),
+        replace_ids: true,
+        ..CompilerConfig::default()
+    };
+
+    let profile_dir = target_dir.join(PROFILE_DIR);
+    let output_dir = match &props.output_dir {
+        Some(output_dir) => profile_dir.join(output_dir),
+        None => profile_dir.clone(),
+    };
+
+    let unit = DemoUnit {
+        name: project_dir
+            .file_name()
+            .unwrap_or("cairo_project")
+            .to_string(),
+        props,
+        root: project_dir.to_path_buf(),
+        ui: ui.clone(),
+    };
+
+    let mut artifacts = ArtifactsWriter::unlocked(output_dir, profile_dir, &unit.name, ui);
+
+    compile_contracts(
+        &mut db,
+        &main_crate_ids,
+        compiler_config,
+        &unit,
+        &mut artifacts,
+    )?;
+
+    artifacts.finish()
+}
@@ -4,22 +4,51 @@
 // NOTE: This files needs to be updated whenever scarb version is updated.
 // NOTE: This file was moved here from `sozo` as we need to compile here too,
 //       and `sozo` has `dojo-lang` as dependency.
-use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
 use cairo_lang_compiler::db::RootDatabase;
+use cairo_lang_compiler::diagnostics::DiagnosticsReporter;
 use cairo_lang_compiler::project::{ProjectConfig, ProjectConfigContent};
-use cairo_lang_filesystem::db::{CrateSettings, ExperimentalFeaturesConfig};
+use cairo_lang_defs::plugin::InlineMacroExprPlugin;
+use cairo_lang_diagnostics::{FormattedDiagnosticEntry, Severity};
+use cairo_lang_filesystem::db::{CrateSettings, ExperimentalFeaturesConfig, FilesGroup};
 use cairo_lang_filesystem::ids::Directory;
+use cairo_lang_formatter::format_string;
 use cairo_lang_project::AllCratesConfig;
+use cairo_lang_semantic::plugin::PluginSuite;
+use cairo_lang_starknet::contract::find_contracts;
 use cairo_lang_starknet::starknet_plugin_suite;
 use cairo_lang_test_plugin::test_plugin_suite;
 use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+use cairo_lang_utils::Upcast;
 use camino::Utf8PathBuf;
-use scarb::compiler::{CairoCompilationUnit, CompilationUnit, CompilationUnitAttributes};
-use scarb::core::Config;
-use scarb::ops::{CompileOpts, FeaturesOpts, FeaturesSelector};
+use scarb::compiler::helpers::collect_main_crate_ids;
+use scarb::compiler::plugin::proc_macro::{
+    compile_unit as compile_proc_macro_unit, ProcMacroHost, ProcMacroHostPlugin,
+};
+use scarb::compiler::{
+    CairoCompilationUnit, CompilationUnit, CompilationUnitAttributes, CompilationUnitComponent,
+    ProcMacroCompilationUnit,
+};
+use scarb::core::{Config, PackageId, TargetKind, Workspace};
+use scarb::ops::CompileOpts;
+use scarb_ui::components::Status;
+use scarb_ui::Ui;
 use smol_str::SmolStr;
 
-use crate::plugin::demo_plugin_suite;
+use crate::artifacts::{clean_target_dir, recorded_artifacts, recorded_artifacts_exist};
+use crate::compiler::DEMO_TARGET_KIND;
+use crate::expansion::contract_generated_files;
+use crate::fingerprint::Fingerprint;
+use crate::plugin::{demo_plugin_cfg, scoped_demo_plugin_suite, PACKAGE_NAME};
+use crate::version::check_cairo_version;
 
 /// Compilation information of all the units found in the workspace.
 #[derive(Debug, Default)]
@@ -34,6 +63,142 @@ pub struct CompileInfo {
     pub root_package_name: Option<String>,
     /// The list of units that failed to compile.
     pub compile_error_units: Vec<String>,
+    /// The result of each unit, in the compilation order.
+    pub units: Vec<UnitInfo>,
+}
+
+/// Compilation result of a single unit.
+#[derive(Debug, Clone)]
+pub struct UnitInfo {
+    /// The name of the unit.
+    pub name: String,
+    /// The target kind of the main component of the unit.
+    pub target_kind: String,
+    pub status: UnitStatus,
+    /// The artifacts of the unit, only known for units of the demo target.
+    pub artifacts: Vec<Utf8PathBuf>,
+    /// The diagnostics of the main crates of the unit, empty for fresh units as they are not
+    /// analyzed.
+    pub diagnostics: Vec<FormattedDiagnosticEntry>,
+    /// The error that made the compilation fail, if any.
+    pub error: Option<String>,
+    /// The errors that didn't make the compilation fail, such as a fingerprint that could not be
+    /// computed or stored, which only forces the next build of the unit.
+    pub warnings: Vec<String>,
+    /// Wall-clock time spent building and analyzing the database of the unit.
+    pub analysis_time: Duration,
+    /// Wall-clock time spent running the compiler of the unit.
+    pub compilation_time: Duration,
+}
+
+impl UnitInfo {
+    fn new(unit: &impl CompilationUnitAttributes, status: UnitStatus) -> Self {
+        Self {
+            name: unit.name(),
+            target_kind: unit.main_component().target_kind().to_string(),
+            status,
+            artifacts: vec![],
+            diagnostics: vec![],
+            error: None,
+            warnings: vec![],
+            analysis_time: Duration::ZERO,
+            compilation_time: Duration::ZERO,
+        }
+    }
+
+    /// Marks the unit as failed because of `err`.
+    fn fail(&mut self, err: &anyhow::Error) {
+        self.status = UnitStatus::Failed;
+        self.error = Some(format!("{err:?}"));
+    }
+
+    /// Records an error that doesn't make the compilation fail.
+    fn warn(&mut self, err: &anyhow::Error, ui: &Ui) {
+        ui.warn(format!("{err:?}"));
+        self.warnings.push(format!("{err:?}"));
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.count_diagnostics(Severity::Error)
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.count_diagnostics(Severity::Warning)
+    }
+
+    fn count_diagnostics(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity() == severity)
+            .count()
+    }
+}
+
+/// The outcome of the compilation of a unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitStatus {
+    /// The unit has been compiled.
+    Compiled,
+    /// The unit was skipped as its inputs did not change since the last build.
+    Fresh,
+    /// The unit has been analyzed without errors, but not compiled.
+    Checked,
+    /// The compilation of the unit failed.
+    Failed,
+}
+
+/// Options of [`compile_workspace`] that are not covered by Scarb's [`CompileOpts`].
+#[derive(Debug, Clone)]
+pub struct BuildOpts {
+    /// The maximum number of compilation units analyzed concurrently, the compilation itself runs
+    /// one unit at a time.
+    pub jobs: NonZeroUsize,
+    /// The workspace members to compile.
+    pub packages: PackagesSelector,
+    /// Builds the database of each unit, with the extra plugins of the caller.
+    pub db_builder: ScarbDatabaseBuilder,
+    /// Compiles the packages even if they require another version of Cairo than the Scarb
+    /// version linked in.
+    pub ignore_cairo_version: bool,
+}
+
+impl Default for BuildOpts {
+    fn default() -> Self {
+        Self {
+            jobs: thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+            packages: PackagesSelector::default(),
+            db_builder: ScarbDatabaseBuilder::default(),
+            ignore_cairo_version: false,
+        }
+    }
+}
+
+/// Selects the workspace members to compile.
+#[derive(Debug, Clone, Default)]
+pub enum PackagesSelector {
+    /// All the members of the workspace.
+    #[default]
+    Workspace,
+    /// The members with the given names.
+    Names(Vec<String>),
+}
+
+impl PackagesSelector {
+    /// Returns the ids of the selected members, failing if a name is not a workspace member.
+    fn select(&self, ws: &Workspace<'_>) -> Result<Vec<PackageId>> {
+        match self {
+            Self::Workspace => Ok(ws.members().map(|p| p.id).collect()),
+            Self::Names(names) => names
+                .iter()
+                .map(|name| {
+                    ws.members()
+                        .find(|p| p.id.name.as_str() == name)
+                        .map(|p| p.id)
+                        .with_context(|| format!("package `{name}` not found in workspace"))
+                })
+                .collect(),
+        }
+    }
 }
 
 pub fn crates_config_for_compilation_unit(unit: &CairoCompilationUnit) -> AllCratesConfig {
@@ -42,19 +207,30 @@ pub fn crates_config_for_compilation_unit(unit: &CairoCompilationUnit) -> AllCra
         .iter()
         .map(|component| {
             // Ensure experimental features are only enable if required.
-            let experimental_features = component.package.manifest.experimental_features.clone();
-            let experimental_features = experimental_features.unwrap_or_default();
+            let experimental_features = component
+                .package
+                .manifest
+                .experimental_features
+                .as_deref()
+                .unwrap_or_default();
+
+            let cfg_set = if depends_on_demo_plugin(component) {
+                let mut cfg_set = component
+                    .cfg_set
+                    .clone()
+                    .unwrap_or_else(|| unit.cfg_set.clone());
+                cfg_set.insert(demo_plugin_cfg());
+                Some(cfg_set)
+            } else {
+                component.cfg_set.clone()
+            };
 
             (
                 component.cairo_package_name(),
                 CrateSettings {
                     edition: component.package.manifest.edition,
-                    experimental_features: ExperimentalFeaturesConfig {
-                        negative_impls: experimental_features
-                            .contains(&SmolStr::new_inline("negative_impls")),
-                        coupons: experimental_features.contains(&SmolStr::new_inline("coupons")),
-                    },
-                    cfg_set: component.cfg_set.clone(),
+                    experimental_features: experimental_features_config(experimental_features),
+                    cfg_set,
                     version: Some(component.package.id.version.clone()),
                 },
             )
@@ -67,69 +243,583 @@ pub fn crates_config_for_compilation_unit(unit: &CairoCompilationUnit) -> AllCra
     }
 }
 
+/// Checks if the package of a component declares a dependency on the demo plugin.
+fn depends_on_demo_plugin(component: &CompilationUnitComponent) -> bool {
+    component
+        .package
+        .manifest
+        .summary
+        .full_dependencies()
+        .any(|dep| dep.name.as_str() == PACKAGE_NAME)
+}
+
+/// The experimental features supported by the linked cairo-lang version, with the setter enabling
+/// them in the compiler configuration.
+const EXPERIMENTAL_FEATURES: &[(&str, fn(&mut ExperimentalFeaturesConfig))] = &[
+    ("negative_impls", |config| config.negative_impls = true),
+    ("coupons", |config| config.coupons = true),
+];
+
+/// Maps the experimental features enabled in a package manifest to the compiler configuration.
+fn experimental_features_config(features: &[SmolStr]) -> ExperimentalFeaturesConfig {
+    let mut config = ExperimentalFeaturesConfig::default();
+
+    for (name, enable) in EXPERIMENTAL_FEATURES {
+        if features.iter().any(|f| f == name) {
+            enable(&mut config);
+        }
+    }
+
+    // No rest pattern on purpose: a cairo-lang update adding a feature fails to build until it is
+    // listed here, as a reminder to add it to `EXPERIMENTAL_FEATURES`.
+    let ExperimentalFeaturesConfig {
+        negative_impls: _,
+        coupons: _,
+    } = config;
+
+    config
+}
+
+/// Warns about the experimental features of the units packages that are not supported by the
+/// compiler, and would be ignored otherwise.
+fn warn_unknown_experimental_features(units: &[CairoCompilationUnit], config: &Config) {
+    let mut checked_packages = HashSet::new();
+
+    for component in units.iter().flat_map(|unit| unit.components()) {
+        if !checked_packages.insert(component.package.id) {
+            continue;
+        }
+
+        let features = component
+            .package
+            .manifest
+            .experimental_features
+            .as_deref()
+            .unwrap_or_default();
+
+        for feature in features {
+            if !EXPERIMENTAL_FEATURES
+                .iter()
+                .any(|(name, _)| name == feature)
+            {
+                config.ui().warn(format!(
+                    "package `{}` enables unknown experimental feature `{feature}`, it will be \
+                     ignored\nsupported experimental features: {}",
+                    component.package.id.name,
+                    EXPERIMENTAL_FEATURES
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+}
+
+/// A unit database, with the procedural macros it has been built with.
+pub struct ScarbDatabase {
+    pub db: RootDatabase,
+    /// Runs the post processing of the procedural macros, once the unit has been compiled.
+    pub proc_macro_host: Arc<ProcMacroHostPlugin>,
+}
+
 /// Builds the scarb root database injecting the dojo plugin suite, additionaly to the
-/// default Starknet and Test suites.
-pub fn build_scarb_root_database(unit: &CairoCompilationUnit) -> Result<RootDatabase> {
-    let mut b = RootDatabase::builder();
-    b.with_project_config(build_project_config(unit)?);
-    b.with_cfg(unit.cfg_set.clone());
+/// default Starknet and Test suites, and the procedural macros the unit depends on.
+///
+/// The demo plugin only expands the crates whose package depends on it.
+pub fn build_scarb_root_database(
+    unit: &CairoCompilationUnit,
+    ws: &Workspace<'_>,
+) -> Result<ScarbDatabase> {
+    let proc_macro_host = load_proc_macros(unit, ws.config())?;
+    let db = ScarbDatabaseBuilder::default().build(unit, proc_macro_host.clone())?;
 
-    b.with_plugin_suite(test_plugin_suite());
-    b.with_plugin_suite(demo_plugin_suite());
-    b.with_plugin_suite(starknet_plugin_suite());
+    Ok(ScarbDatabase {
+        db,
+        proc_macro_host,
+    })
+}
+
+/// Loads the procedural macros the unit depends on, which must have been compiled before.
+///
+/// The builtin plugins are skipped, the demo and Starknet suites being always installed.
+pub fn load_proc_macros(
+    unit: &CairoCompilationUnit,
+    config: &Config,
+) -> Result<Arc<ProcMacroHostPlugin>> {
+    let mut proc_macros = ProcMacroHost::default();
+
+    for plugin in unit.cairo_plugins.iter().filter(|p| !p.builtin) {
+        proc_macros
+            .register(plugin.package.clone(), config)
+            .with_context(|| {
+                format!(
+                    "failed to load procedural macro `{}` for compilation unit `{}`",
+                    plugin.package.id,
+                    unit.name()
+                )
+            })?;
+    }
 
-    b.build()
+    Ok(Arc::new(proc_macros.into_plugin()?))
 }
 
-/// This function is an alternative to `ops::compile`, it's doing the same job.
-/// However, we can control the injection of the plugins, required to have dojo plugin present
-/// for each compilation.
-pub fn compile_workspace(config: &Config, opts: CompileOpts) -> Result<CompileInfo> {
-    let ws = scarb::ops::read_workspace(config.manifest_path(), config)?;
-    let packages: Vec<scarb::core::PackageId> = ws.members().map(|p| p.id).collect();
-    let resolve = scarb::ops::resolve_workspace(&ws)?;
+/// Builds the databases of Scarb compilation units with the Test, demo and Starknet plugin
+/// suites, and the extra plugins of the caller.
+#[derive(Debug, Clone, Default)]
+pub struct ScarbDatabaseBuilder {
+    extra_plugin_suite: PluginSuite,
+}
 
-    let features_opts = FeaturesOpts {
-        features: FeaturesSelector::AllFeatures,
-        no_default_features: false,
-    };
+impl ScarbDatabaseBuilder {
+    /// Adds the plugins of a suite, after the default ones.
+    pub fn with_plugin_suite(&mut self, suite: PluginSuite) -> &mut Self {
+        self.extra_plugin_suite.add(suite);
+        self
+    }
+
+    /// Adds an inline macro plugin, named after the macro it expands.
+    pub fn with_inline_macro_plugin(
+        &mut self,
+        name: &str,
+        plugin: Arc<dyn InlineMacroExprPlugin>,
+    ) -> &mut Self {
+        self.extra_plugin_suite
+            .add_inline_macro_plugin_ex(name, plugin);
+        self
+    }
+
+    /// Builds the database of a compilation unit, with the procedural macros loaded by
+    /// [`load_proc_macros`].
+    pub fn build(
+        &self,
+        unit: &CairoCompilationUnit,
+        proc_macro_host: Arc<ProcMacroHostPlugin>,
+    ) -> Result<RootDatabase> {
+        let mut b = RootDatabase::builder();
+        b.with_project_config(build_project_config(unit)?);
+        b.with_cfg(unit.cfg_set.clone());
+
+        b.with_plugin_suite(test_plugin_suite());
+        b.with_plugin_suite(scoped_demo_plugin_suite());
+        b.with_plugin_suite(starknet_plugin_suite());
+        b.with_plugin_suite(ProcMacroHostPlugin::build_plugin_suite(proc_macro_host));
+        b.with_plugin_suite(self.extra_plugin_suite.clone());
+
+        b.build()
+    }
+}
+
+/// A unit that has to be compiled.
+struct StaleUnit {
+    /// The fingerprint to store once the unit is compiled, only computed for units of the demo
+    /// target.
+    fingerprint: Option<Result<Fingerprint>>,
+    /// The procedural macros of the unit, loaded before building its database.
+    proc_macro_host: Result<Arc<ProcMacroHostPlugin>>,
+}
+
+/// A unit database, built and analyzed before the compilation.
+struct PreparedUnit {
+    db: RootDatabase,
+    diagnostics: Vec<FormattedDiagnosticEntry>,
+}
+
+/// Builds the database of a unit, and runs the semantic and lowering analysis of its main crates
+/// to warm the database before the compilation.
+fn prepare_unit_database(
+    db_builder: &ScarbDatabaseBuilder,
+    unit: &CairoCompilationUnit,
+    proc_macro_host: Arc<ProcMacroHostPlugin>,
+) -> Result<PreparedUnit> {
+    let db = db_builder.build(unit, proc_macro_host).with_context(|| {
+        format!(
+            "failed to build the database of compilation unit `{}`",
+            unit.name()
+        )
+    })?;
+    let main_crate_ids = collect_main_crate_ids(unit, &db);
+
+    // Diagnostics are printed by the compiler, they are only collected here.
+    let mut diagnostics = vec![];
+    DiagnosticsReporter::callback(|entry: FormattedDiagnosticEntry| {
+        if !entry.is_empty() {
+            diagnostics.push(entry);
+        }
+    })
+    .with_crates(&main_crate_ids)
+    .check(&db);
+
+    Ok(PreparedUnit { db, diagnostics })
+}
+
+/// Generates the compilation units of the selected packages, the way Scarb does, along with the
+/// procedural macros they depend on.
+///
+/// The Cairo version required by the selected packages is checked first, unless ignored.
+fn select_compilation_units(
+    ws: &Workspace<'_>,
+    opts: &CompileOpts,
+    build_opts: &BuildOpts,
+) -> Result<(Vec<CairoCompilationUnit>, Vec<ProcMacroCompilationUnit>)> {
+    let packages = build_opts.packages.select(ws)?;
+
+    if !build_opts.ignore_cairo_version {
+        let members = ws
+            .members()
+            .filter(|p| packages.contains(&p.id))
+            .collect::<Vec<_>>();
+        check_cairo_version(&members, ws.config())?;
+    }
+    let resolve = scarb::ops::resolve_workspace(ws)?;
 
-    let compilation_units = scarb::ops::generate_compilation_units(&resolve, &features_opts, &ws)?
+    let compilation_units = scarb::ops::generate_compilation_units(&resolve, &opts.features, ws)?
         .into_iter()
         .filter(|cu| {
-            !opts
-                .exclude_target_kinds
-                .contains(&cu.main_component().target_kind())
-        })
-        .filter(|cu| {
-            opts.include_target_kinds.is_empty()
-                || opts
-                    .include_target_kinds
-                    .contains(&cu.main_component().target_kind())
+            let target_kind = cu.main_component().target_kind();
+            let is_excluded = opts.exclude_target_kinds.contains(&target_kind);
+            let is_included = opts.include_target_kinds.is_empty()
+                || opts.include_target_kinds.contains(&target_kind);
+            let is_included = is_included
+                && (opts.include_target_names.is_empty()
+                    || cu
+                        .main_component()
+                        .targets
+                        .iter()
+                        .any(|t| opts.include_target_names.contains(&t.name)));
+            let is_selected = packages.contains(&cu.main_package_id());
+
+            // The procedural macros are required by the units depending on them, whatever the
+            // selection.
+            let is_proc_macro = matches!(cu, CompilationUnit::ProcMacro(_));
+
+            is_proc_macro || (is_selected && is_included && !is_excluded)
         })
-        .filter(|cu| packages.contains(&cu.main_package_id()))
         .collect::<Vec<_>>();
 
-    let mut compile_error_units = vec![];
+    let mut units = vec![];
+    let mut proc_macro_units = vec![];
     for unit in compilation_units {
-        if let CompilationUnit::Cairo(unit) = unit {
-            let mut db = build_scarb_root_database(&unit).unwrap();
+        match unit {
+            CompilationUnit::Cairo(unit) => units.push(unit),
+            CompilationUnit::ProcMacro(unit) => proc_macro_units.push(unit),
+        }
+    }
+
+    warn_unknown_experimental_features(&units, ws.config());
+
+    Ok((units, proc_macro_units))
+}
+
+/// Compiles the procedural macro units, which must be done before building the databases of the
+/// Cairo units depending on them.
+///
+/// Errors are reported, and the units that failed to compile are marked as such. The packages of
+/// the procedural macros that failed are also returned, for the units depending on them to fail
+/// too.
+fn compile_proc_macro_units(
+    units: Vec<ProcMacroCompilationUnit>,
+    ws: &Workspace<'_>,
+) -> (Vec<UnitInfo>, HashSet<PackageId>) {
+    let mut unit_infos = vec![];
+    let mut failed_packages = HashSet::new();
+
+    for unit in units {
+        let mut unit_info = UnitInfo::new(&unit, UnitStatus::Compiled);
+        let package_id = unit.main_package_id();
+
+        ws.config()
+            .ui()
+            .print(Status::new("Compiling", &unit_info.name));
+
+        let start = Instant::now();
+        let result = compile_proc_macro_unit(unit, ws);
+        unit_info.compilation_time = start.elapsed();
+
+        if let Err(err) = result {
+            ws.config().ui().anyhow(&err);
+            unit_info.fail(&err);
+            failed_packages.insert(package_id);
+        }
+
+        unit_infos.push(unit_info);
+    }
+
+    (unit_infos, failed_packages)
+}
+
+/// Loads the procedural macros of a unit once they have been compiled, failing if one of them
+/// could not be, instead of loading the library left by a previous build.
+fn load_compiled_proc_macros(
+    unit: &CairoCompilationUnit,
+    config: &Config,
+    failed_packages: &HashSet<PackageId>,
+) -> Result<Arc<ProcMacroHostPlugin>> {
+    let failed_plugin = unit
+        .cairo_plugins
+        .iter()
+        .find(|p| !p.builtin && failed_packages.contains(&p.package.id));
+
+    if let Some(plugin) = failed_plugin {
+        bail!(
+            "procedural macro `{}` of compilation unit `{}` failed to compile",
+            plugin.package.id,
+            unit.name()
+        );
+    }
 
-            if let Err(err) = ws
+    load_proc_macros(unit, config)
+}
+
+/// Runs `prepare` on each unit concurrently, on at most `jobs` threads, and `process` on the
+/// prepared units in the units order, on the calling thread.
+///
+/// A unit is only prepared once the unit `jobs` places before it has been processed, to bound the
+/// number of prepared units (and their databases) waiting to be processed.
+///
+/// A panic while preparing a unit is resumed on the calling thread, once the other workers have
+/// been released.
+fn prepare_units_in_order<U: Sync, T: Send>(
+    units: &[U],
+    jobs: NonZeroUsize,
+    prepare: impl Fn(usize, &U) -> T + Sync,
+    mut process: impl FnMut(usize, T) -> Result<()>,
+) -> Result<()> {
+    let jobs = jobs.get();
+    let next_unit = AtomicUsize::new(0);
+    let progress = (Mutex::new(Progress::default()), Condvar::new());
+
+    thread::scope(|s| {
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..jobs.min(units.len()) {
+            let sender = sender.clone();
+            let (next_unit, progress, prepare) = (&next_unit, &progress, &prepare);
+
+            s.spawn(move || loop {
+                let index = next_unit.fetch_add(1, Ordering::Relaxed);
+                let Some(unit) = units.get(index) else {
+                    break;
+                };
+
+                // Waits for the processing to catch up before preparing the unit.
+                let (lock, condvar) = progress;
+                let stopped = condvar
+                    .wait_while(lock.lock().unwrap(), |progress| {
+                        !progress.stopped && index >= progress.processed + jobs
+                    })
+                    .unwrap()
+                    .stopped;
+                if stopped {
+                    break;
+                }
+
+                let prepared = panic::catch_unwind(AssertUnwindSafe(|| prepare(index, unit)));
+                if sender.send((index, prepared)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Releases the waiting workers once the processing is over, successfully or not, even if
+        // it panics.
+        let _stop = StopOnDrop(&progress);
+
+        let mut prepared_units = BTreeMap::new();
+        let mut index = 0;
+
+        for (unit_index, prepared) in &receiver {
+            match prepared {
+                Ok(prepared) => prepared_units.insert(unit_index, prepared),
+                Err(payload) => panic::resume_unwind(payload),
+            };
+
+            while let Some(prepared) = prepared_units.remove(&index) {
+                process(index, prepared)?;
+                index += 1;
+
+                let (lock, condvar) = &progress;
+                lock.lock().unwrap().processed = index;
+                condvar.notify_all();
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// The progress of the processing of the units, shared with the workers preparing them.
+#[derive(Debug, Default)]
+struct Progress {
+    /// The number of units processed, in the units order.
+    processed: usize,
+    /// Set once the processing is over, successfully or not.
+    stopped: bool,
+}
+
+/// Stops the workers of [`prepare_units_in_order`] when dropped.
+struct StopOnDrop<'a>(&'a (Mutex<Progress>, Condvar));
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        let (lock, condvar) = self.0;
+        // The lock can't be poisoned, the workers don't panic while holding it.
+        lock.lock().unwrap().stopped = true;
+        condvar.notify_all();
+    }
+}
+
+/// This function is an alternative to `ops::compile`, it's doing the same job.
+/// However, we can control the injection of the plugins, required to have dojo plugin present
+/// for each compilation.
+pub fn compile_workspace(
+    config: &Config,
+    opts: CompileOpts,
+    build_opts: BuildOpts,
+) -> Result<CompileInfo> {
+    let ws = scarb::ops::read_workspace(config.manifest_path(), config)?;
+    let (units, proc_macro_units) = select_compilation_units(&ws, &opts, &build_opts)?;
+
+    // The procedural macros are compiled first, as they are loaded to build the Cairo units
+    // databases.
+    let (mut unit_infos, failed_proc_macros) = compile_proc_macro_units(proc_macro_units, &ws);
+    let mut compile_error_units = unit_infos
+        .iter()
+        .filter(|unit_info| unit_info.status == UnitStatus::Failed)
+        .map(|unit_info| unit_info.name.clone())
+        .collect::<Vec<_>>();
+
+    let mut stale_units = vec![];
+    for unit in &units {
+        let profile_dir = unit.target_dir(&ws);
+        let unit_name = unit.name();
+
+        // Only the artifacts of the demo target are recorded, the units of other targets are
+        // always compiled as their outputs can't be checked. A unit whose fingerprint can't be
+        // computed is compiled, the error being reported with the unit.
+        let is_demo = unit.main_component().target_kind() == TargetKind::new(DEMO_TARGET_KIND);
+        let fingerprint = is_demo.then(|| {
+            Fingerprint::compute(unit, &ws)
+                .with_context(|| format!("failed to fingerprint compilation unit `{unit_name}`"))
+        });
+        let is_fresh = match &fingerprint {
+            Some(Ok(fingerprint)) => {
+                fingerprint.is_fresh(&profile_dir, &unit_name)
+                    && recorded_artifacts_exist(&profile_dir, &unit_name)
+            }
+            _ => false,
+        };
+
+        // The procedural macros are loaded here, as it requires the config which can't be shared
+        // with the workers.
+        stale_units.push((!is_fresh).then(|| StaleUnit {
+            fingerprint,
+            proc_macro_host: load_compiled_proc_macros(unit, ws.config(), &failed_proc_macros),
+        }));
+    }
+
+    // The databases are built and analyzed concurrently, which is the most expensive part of the
+    // compilation. The compilers are then run in the units order, to keep the output of each unit
+    // grouped and deterministic.
+    let db_builder = &build_opts.db_builder;
+    prepare_units_in_order(
+        &units,
+        build_opts.jobs,
+        |index, unit| {
+            stale_units[index].as_ref().map(|stale| {
+                let start = Instant::now();
+                let prepared = match &stale.proc_macro_host {
+                    Ok(host) => prepare_unit_database(db_builder, unit, host.clone()),
+                    Err(err) => Err(anyhow!("{err:?}")),
+                };
+                (prepared, start.elapsed())
+            })
+        },
+        |index, prepared| {
+            let unit = &units[index];
+            let stale = &stale_units[index];
+            let profile_dir = unit.target_dir(&ws);
+            let unit_name = unit.name();
+
+            let mut unit_info = UnitInfo::new(unit, UnitStatus::Fresh);
+
+            let (Some((prepared, analysis_time)), Some(stale)) = (prepared, stale) else {
+                ws.config()
+                    .ui()
+                    .print(format!("Skipping fresh compilation unit {unit_name}."));
+
+                unit_info.artifacts = recorded_artifacts(&profile_dir, &unit_name);
+                unit_infos.push(unit_info);
+                return Ok(());
+            };
+
+            ws.config().ui().print(Status::new("Compiling", &unit_name));
+
+            if let Some(Err(err)) = &stale.fingerprint {
+                unit_info.warn(err, &ws.config().ui());
+            }
+
+            unit_info.analysis_time = analysis_time;
+
+            let PreparedUnit {
+                mut db,
+                diagnostics,
+            } = match prepared {
+                Ok(prepared) => prepared,
+                Err(err) => {
+                    ws.config().ui().anyhow(&err);
+                    Fingerprint::invalidate(&profile_dir, &unit_name);
+                    compile_error_units.push(unit_name);
+
+                    unit_info.fail(&err);
+                    unit_infos.push(unit_info);
+                    return Ok(());
+                }
+            };
+
+            unit_info.diagnostics = diagnostics;
+
+            let start = Instant::now();
+            let result = ws
                 .config()
                 .compilers()
                 .compile(unit.clone(), &mut (db), &ws)
-            {
+                .and_then(|_| match &stale.proc_macro_host {
+                    Ok(host) => host.post_process(db.upcast()),
+                    Err(_) => Ok(()),
+                });
+            unit_info.compilation_time = start.elapsed();
+
+            if let Err(err) = result {
                 ws.config().ui().anyhow(&err);
-                compile_error_units.push(unit.name());
+                Fingerprint::invalidate(&profile_dir, &unit_name);
+                compile_error_units.push(unit_name);
+
+                unit_info.fail(&err);
+            } else {
+                if let Some(Ok(fingerprint)) = &stale.fingerprint {
+                    if let Err(err) = fingerprint.store(&profile_dir, &unit_name) {
+                        Fingerprint::invalidate(&profile_dir, &unit_name);
+                        unit_info.warn(&err, &ws.config().ui());
+                    }
+                }
+
+                unit_info.status = UnitStatus::Compiled;
+                unit_info.artifacts = recorded_artifacts(&profile_dir, &unit_name);
             }
-        } else {
-            println!("Skipping compilation unit {}.", unit.name());
-        }
-    }
+
+            unit_infos.push(unit_info);
+            Ok(())
+        },
+    )?;
 
     let manifest_path = ws.manifest_path().into();
-    let target_dir = ws.target_dir().path_existent().unwrap();
+    let target_dir = ws
+        .target_dir()
+        .path_existent()
+        .context("failed to create the target directory")?;
     let target_dir = target_dir.join(ws.config().profile().as_str());
 
     // The root package may be non existent in a scarb project/workspace.
@@ -152,9 +842,143 @@ pub fn compile_workspace(config: &Config, opts: CompileOpts) -> Result<CompileIn
         root_package_name,
         profile_name,
         compile_error_units,
+        units: unit_infos,
     })
 }
 
+/// Reports the diagnostics of the selected units, running the parsing, the plugins expansion and
+/// the semantic and lowering analysis, without compiling the contracts nor writing artifacts.
+///
+/// All the units are analyzed, whatever their fingerprints. Only the procedural macros the units
+/// depend on are compiled, as they are required to expand the code.
+///
+/// Units with errors are marked as failed, the caller decides how to report them.
+pub fn check_workspace(
+    config: &Config,
+    opts: CompileOpts,
+    build_opts: BuildOpts,
+) -> Result<Vec<UnitInfo>> {
+    let ws = scarb::ops::read_workspace(config.manifest_path(), config)?;
+    let (units, proc_macro_units) = select_compilation_units(&ws, &opts, &build_opts)?;
+
+    let (mut unit_infos, failed_proc_macros) = compile_proc_macro_units(proc_macro_units, &ws);
+
+    // The procedural macros are loaded here, as it requires the config which can't be shared
+    // with the workers.
+    let proc_macro_hosts = units
+        .iter()
+        .map(|unit| load_compiled_proc_macros(unit, ws.config(), &failed_proc_macros))
+        .collect::<Vec<_>>();
+
+    // The units are analyzed concurrently, and reported in order as for the compilation.
+    let db_builder = &build_opts.db_builder;
+    prepare_units_in_order(
+        &units,
+        build_opts.jobs,
+        |index, unit| {
+            let start = Instant::now();
+            let prepared = match &proc_macro_hosts[index] {
+                Ok(host) => prepare_unit_database(db_builder, unit, host.clone()),
+                Err(err) => Err(anyhow!("{err:?}")),
+            };
+            // The database is dropped by the worker, only the diagnostics are reported.
+            (
+                prepared.map(|prepared| prepared.diagnostics),
+                start.elapsed(),
+            )
+        },
+        |index, (diagnostics, analysis_time)| {
+            let unit = &units[index];
+
+            ws.config()
+                .ui()
+                .print(Status::new("Checking", &unit.name()));
+
+            let mut unit_info = UnitInfo::new(unit, UnitStatus::Checked);
+            unit_info.analysis_time = analysis_time;
+
+            match diagnostics {
+                Ok(diagnostics) => {
+                    for entry in &diagnostics {
+                        let message = entry.message().trim_end();
+                        match entry.severity() {
+                            Severity::Error => ws.config().ui().error(message),
+                            Severity::Warning => ws.config().ui().warn(message),
+                        }
+                    }
+
+                    unit_info.diagnostics = diagnostics;
+                    if unit_info.error_count() > 0 {
+                        unit_info.status = UnitStatus::Failed;
+                    }
+                }
+                Err(err) => {
+                    ws.config().ui().anyhow(&err);
+                    unit_info.fail(&err);
+                }
+            }
+
+            unit_infos.push(unit_info);
+            Ok(())
+        },
+    )?;
+
+    Ok(unit_infos)
+}
+
+/// Prints the code generated by the demo plugin for the contracts of the selected packages,
+/// formatted, without compiling them.
+///
+/// Each package is expanded once, with its first compilation unit. The procedural macros the
+/// packages depend on are compiled first.
+pub fn expand_workspace(config: &Config, opts: CompileOpts, build_opts: BuildOpts) -> Result<()> {
+    let ws = scarb::ops::read_workspace(config.manifest_path(), config)?;
+    let (units, proc_macro_units) = select_compilation_units(&ws, &opts, &build_opts)?;
+
+    let (proc_macro_infos, _) = compile_proc_macro_units(proc_macro_units, &ws);
+    if let Some(unit_info) = proc_macro_infos
+        .iter()
+        .find(|unit_info| unit_info.status == UnitStatus::Failed)
+    {
+        bail!(
+            "could not compile procedural macro unit `{}`",
+            unit_info.name
+        );
+    }
+
+    let mut expanded_packages = HashSet::new();
+    for unit in units
+        .iter()
+        .filter(|unit| expanded_packages.insert(unit.main_package_id()))
+    {
+        let proc_macro_host = load_proc_macros(unit, ws.config())?;
+        let db = build_opts.db_builder.build(unit, proc_macro_host)?;
+        let main_crate_ids = collect_main_crate_ids(unit, &db);
+
+        for decl in find_contracts(&db, &main_crate_ids) {
+            for generated_file in contract_generated_files(&db, decl.submodule_id) {
+                let path = generated_file.relative_path(&db);
+                let content = db
+                    .file_content(generated_file.file_id)
+                    .with_context(|| format!("failed to get expanded file content: {path}"))?;
+
+                ws.config().ui().print(format!(
+                    "// {path}\n{}",
+                    format_string(&db, content.to_string())
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes all the artifacts produced by the demo target in the workspace target directory.
+pub fn clean_workspace(config: &Config) -> Result<()> {
+    let ws = scarb::ops::read_workspace(config.manifest_path(), config)?;
+    clean_target_dir(ws.target_dir().path_unchecked(), config)
+}
+
 fn build_project_config(unit: &CairoCompilationUnit) -> Result<ProjectConfig> {
     let crate_roots = unit
         .components()
@@ -190,3 +1014,75 @@ fn build_project_config(unit: &CairoCompilationUnit) -> Result<ProjectConfig> {
 
     Ok(project_config)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::panic;
+
+    use super::prepare_units_in_order;
+
+    #[test]
+    fn processes_prepared_units_in_order() {
+        let units = (0..16).collect::<Vec<usize>>();
+        let mut processed = vec![];
+
+        prepare_units_in_order(
+            &units,
+            NonZeroUsize::new(4).unwrap(),
+            |index, unit| (index, unit * 2),
+            |index, prepared| {
+                processed.push((index, prepared));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let expected = units.iter().map(|u| (*u, (*u, u * 2))).collect::<Vec<_>>();
+        assert_eq!(processed, expected);
+    }
+
+    #[test]
+    fn stops_on_processing_error() {
+        let units = (0..16).collect::<Vec<usize>>();
+        let mut processed = vec![];
+
+        let result = prepare_units_in_order(
+            &units,
+            NonZeroUsize::new(2).unwrap(),
+            |_, unit| *unit,
+            |index, _| {
+                if index == 3 {
+                    anyhow::bail!("processing failed");
+                }
+                processed.push(index);
+                Ok(())
+            },
+        );
+
+        assert!(result.is_err());
+        assert_eq!(processed, [0, 1, 2]);
+    }
+
+    #[test]
+    fn propagates_panics_while_preparing() {
+        let units = (0..16).collect::<Vec<usize>>();
+
+        let result = panic::catch_unwind(|| {
+            prepare_units_in_order(
+                &units,
+                NonZeroUsize::new(2).unwrap(),
+                |_, unit| {
+                    if *unit == 1 {
+                        panic!("prepare failed");
+                    }
+                    *unit
+                },
+                |_, _| Ok(()),
+            )
+        });
+
+        let payload = result.expect_err("the panic must be propagated");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"prepare failed"));
+    }
+}
@@ -0,0 +1,142 @@
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_filesystem::ids::{CodeOrigin, FileId, FileLongId};
+use cairo_lang_filesystem::span::{TextOffset, TextSpan};
+use cairo_lang_utils::Upcast;
+use serde::{Deserialize, Serialize};
+
+/// Extension of the source map written next to each expanded source.
+pub const SOURCE_MAP_EXTENSION: &str = "map.json";
+
+/// Extension of the file written next to each expanded source, as produced by the plugin before
+/// formatting, which the source map refers to.
+pub const GENERATED_SOURCE_EXTENSION: &str = "generated.cairo";
+
+/// Links the ranges of a plugin generated file to the original source they come from.
+///
+/// The generated ranges refer to the file exactly as produced by the plugin, unformatted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceMap {
+    /// The name of the generated virtual file.
+    pub file: String,
+    /// The mapped ranges, in the order of the generated file.
+    pub mappings: Vec<SourceMapping>,
+}
+
+/// A range of the generated file and where it originates from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceMapping {
+    /// The range in the generated file.
+    pub generated: Range,
+    /// The range in the original file.
+    pub original: OriginalRange,
+}
+
+/// A range inside the original file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OriginalRange {
+    /// The path of the original file, or the full path of a virtual file if the code could not
+    /// be mapped back to a file on disk.
+    pub file: String,
+    #[serde(flatten)]
+    pub range: Range,
+}
+
+/// A range inside a file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A position inside a file, all values are 0 based.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Position {
+    /// Byte offset from the beginning of the file.
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Builds the source map of a plugin generated file from its code mappings.
+///
+/// Returns `None` if the file is not a virtual file.
+pub fn build_source_map(db: &dyn DefsGroup, file_id: FileId) -> Option<SourceMap> {
+    let FileLongId::Virtual(vf) = db.lookup_intern_file(file_id) else {
+        return None;
+    };
+
+    let parent = vf.parent?;
+
+    let mappings = vf
+        .code_mappings
+        .iter()
+        .filter_map(|mapping| {
+            let origin_span = match mapping.origin {
+                CodeOrigin::Start(start) => TextSpan {
+                    start,
+                    end: start.add_width(mapping.span.width()),
+                },
+                CodeOrigin::Span(span) => span,
+            };
+
+            let (original_file, original_span) = resolve_origin(db, parent, origin_span);
+
+            Some(SourceMapping {
+                generated: range_in_file(db, file_id, mapping.span)?,
+                original: OriginalRange {
+                    file: original_file.full_path(db.upcast()),
+                    range: range_in_file(db, original_file, original_span)?,
+                },
+            })
+        })
+        .collect();
+
+    Some(SourceMap {
+        file: vf.name.to_string(),
+        mappings,
+    })
+}
+
+/// Follows the code mappings of virtual files up to a file on disk, as far as the code is
+/// mapped.
+fn resolve_origin(db: &dyn DefsGroup, file_id: FileId, span: TextSpan) -> (FileId, TextSpan) {
+    let mut file_id = file_id;
+    let mut span = span;
+
+    while let FileLongId::Virtual(vf) = db.lookup_intern_file(file_id) {
+        let Some(parent) = vf.parent else {
+            break;
+        };
+        let Some(translated) = vf.code_mappings.iter().find_map(|m| m.translate(span)) else {
+            break;
+        };
+
+        file_id = parent;
+        span = translated;
+    }
+
+    (file_id, span)
+}
+
+pub(crate) fn range_in_file(db: &dyn DefsGroup, file_id: FileId, span: TextSpan) -> Option<Range> {
+    Some(Range {
+        start: position_in_file(db, file_id, span.start)?,
+        end: position_in_file(db, file_id, span.end)?,
+    })
+}
+
+fn position_in_file(db: &dyn DefsGroup, file_id: FileId, offset: TextOffset) -> Option<Position> {
+    let content = db.file_content(file_id)?;
+    let position = offset.position_in_file(db.upcast(), file_id)?;
+
+    Some(Position {
+        offset: TextSpan {
+            start: TextOffset::default(),
+            end: offset,
+        }
+        .take(&content)
+        .len(),
+        line: position.line,
+        col: position.col,
+    })
+}
@@ -0,0 +1,113 @@
+use anyhow::{bail, Result};
+use scarb::core::{Config, DependencyVersionReq, Package};
+use semver::{Version, VersionReq};
+
+/// The name of the Starknet package, which is versioned along with the Cairo compiler.
+const STARKNET_PACKAGE_NAME: &str = "starknet";
+
+/// The Cairo version of the Scarb the demo compiler is built with.
+///
+/// As `scarb_funcs` mirrors Scarb internals, projects are compiled with this version of Scarb and
+/// Cairo, whatever the version of the `scarb` binary installed. The Scarb and Cairo versions may
+/// differ, the requirements of the packages are checked against the Cairo one as Scarb does.
+pub fn linked_cairo_version() -> Version {
+    Version::parse(&scarb::version::get().cairo.version)
+        .expect("the version of the linked cairo must be valid semver")
+}
+
+/// Checks that the `cairo-version` and the `starknet` dependency declared by the packages match
+/// the Cairo version linked in.
+///
+/// A requirement that doesn't match the linked version is an error, while a requirement matching
+/// it but targeting an older minor version is only reported as a warning, as the package may rely
+/// on a behavior that changed since.
+pub fn check_cairo_version<'a>(
+    packages: impl IntoIterator<Item = &'a Package>,
+    config: &Config,
+) -> Result<()> {
+    let linked = linked_cairo_version();
+    let mut incompatible = false;
+
+    for package in packages {
+        let mut requirements = vec![];
+
+        if let Some(cairo_version) = &package.manifest.metadata.cairo_version {
+            requirements.push(("cairo-version", cairo_version.clone()));
+        }
+
+        for dep in &package.manifest.summary.dependencies {
+            if dep.name.as_str() != STARKNET_PACKAGE_NAME {
+                continue;
+            }
+
+            match &dep.version_req {
+                DependencyVersionReq::Req(req) | DependencyVersionReq::Locked { req, .. } => {
+                    requirements.push((STARKNET_PACKAGE_NAME, req.clone()));
+                }
+                DependencyVersionReq::Any => {}
+            }
+        }
+
+        for (name, req) in requirements {
+            if !req.matches(&linked) {
+                incompatible = true;
+                config.ui().error(format!(
+                    "package `{}` requires {name} `{req}`, but the demo compiler is built with \
+                     Cairo {linked}\nhelp: set {name} to `{linked}` in {}, or build the demo \
+                     compiler with a `scarb` version shipping Cairo `{req}`",
+                    package.id.name,
+                    package.manifest_path(),
+                ));
+            } else if targets_older_minor(&req, &linked) {
+                config.ui().warn(format!(
+                    "package `{}` requires {name} `{req}`, which is older than Cairo {linked} \
+                     the demo compiler is built with\nhelp: consider setting {name} to \
+                     `{linked}` in {}",
+                    package.id.name,
+                    package.manifest_path(),
+                ));
+            }
+        }
+    }
+
+    if incompatible {
+        bail!(
+            "the workspace is not compatible with Cairo {linked}, use `--ignore-cairo-version` to \
+             compile it anyway"
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks if the lowest version of the requirement has an older major or minor version.
+fn targets_older_minor(req: &VersionReq, linked: &Version) -> bool {
+    req.comparators.iter().any(|c| {
+        c.major < linked.major || (c.major == linked.major && c.minor.unwrap_or(0) < linked.minor)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::{Version, VersionReq};
+
+    use super::targets_older_minor;
+
+    fn older(req: &str) -> bool {
+        targets_older_minor(&VersionReq::parse(req).unwrap(), &Version::new(2, 8, 4))
+    }
+
+    #[test]
+    fn detects_older_minor_requirements() {
+        assert!(older("^2.6.0"));
+        assert!(older(">=2.7"));
+        assert!(older("1"));
+    }
+
+    #[test]
+    fn accepts_current_minor_requirements() {
+        assert!(!older("^2.8.0"));
+        assert!(!older("=2.8.4"));
+        assert!(!older("*"));
+    }
+}